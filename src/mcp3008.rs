@@ -1,277 +1,3129 @@
 use std::{
     io,
+    fmt,
+    mem,
+    error,
     thread,
-    sync::mpsc,
+    convert::TryFrom,
+    sync::{
+        mpsc,
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    cell::{
+        Cell,
+        RefCell,
+    },
+    collections::VecDeque,
+    f64::consts::PI,
+    time::{
+        Instant,
+        Duration,
+    },
 };
 
 use rppal::spi::{
     Spi,
+    Segment,
+    BitOrder,
+};
+
+pub use rppal::spi::{
     Bus,
     Mode,
-    Segment,
     SlaveSelect,
 };
 
 use rpi_lfa::Volt;
 
+#[cfg(feature = "serde")]
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
+pub mod mock;
+
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+#[cfg(feature = "tokio")]
+pub mod async_session;
+
 pub enum Session {
     Initializing(Initializing),
     Ready(Ready),
     Probing(Probing),
+    ProbingBatch(ProbingBatch),
+    ScanAll(ScanAll),
+    Streaming(Streaming),
+    Watching(Watching),
+    Debouncing(Debouncing),
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default = "serde_impl::params"))]
 pub struct Params {
     pub voltage_drain: Vdd,
     pub voltage_ref: Vref,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::BusDef"))]
+    pub bus: Bus,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::SlaveSelectDef"))]
+    pub slave_select: SlaveSelect,
+    // Overrides the SPI clock derived from `chip` and `voltage_drain` (1.35 MHz for 3V3, 3.6 MHz for 5V,
+    // 1 MHz and 2 MHz for the 12-bit parts, or 320 kHz and 1.2 MHz for MCP3002).
+    // Staying within the datasheet maximum for the chosen Vdd is the caller's responsibility.
+    pub clock_hz: Option<u32>,
+    // The chip samples on the rising and shifts out on the falling clock edge, which only `Mode0` and `Mode3` provide.
+    // `Mode1` and `Mode2` are passed through as is but read garbage.
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::ModeDef"))]
+    pub mode: Mode,
+    pub chip: Chip,
+    // Overrides where the command and result bits sit in the frame, for clones and bridges which shift them;
+    // `Framing::Standard` is what `chip` dictates.
+    pub framing: Framing,
+    // Number of transfers averaged into each raw sample, 0 and 1 both mean a single transfer.
+    pub oversample: u16,
+    // Combines raw samples into a reading before it is scaled to volts, so a reading costs
+    // `oversample` times the filter length transfers.
+    pub filter: FilterMode,
+    // Gap between the transfers of one reading, both oversampled and filtered ones, to decorrelate noise.
+    // `sample_rate` reports the slower rate which results from it.
+    pub sample_interval: Option<Duration>,
+    // Switching to another input first runs a discarded conversion and then waits this long, so that the
+    // sample-and-hold capacitor can charge through a high impedance source instead of showing cross-talk.
+    // Repeated reads of the same input are not delayed. The `supply_monitor` conversion switches the input too,
+    // so with a monitor configured every reading of another channel pays the delay.
+    pub channel_switch_delay: Option<Duration>,
+    // Transient transfer errors are retried this many times before giving up, initialization is never retried.
+    pub max_retries: u8,
+    pub retry_delay: Duration,
+    // How often the channel is sampled while in `Watching` state.
+    pub watch_interval: Duration,
+    // A watched reading has to come back this far inside the window before it is reported as back in range.
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+    pub watch_hysteresis: Volt,
+    // Per channel correction indexed by channel number, differential readings use the IN+ channel entry.
+    pub calibration: [Calibration; 8],
+    // Worker thread name, defaults to "Mcp3008 spi" followed by the spidev numbering, e.g. "Mcp3008 spi0.1".
+    pub thread_name: Option<String>,
+    // Worker thread stack size, the std default is used when `None`.
+    pub stack_size: Option<usize>,
+    // Fail with `Error::ImplausibleReading` when the null bit preceding the result is not driven low,
+    // which is what a floating MISO line or an unpowered chip looks like. An all-zeros reply can not be told apart from 0 V.
+    pub check_null_bit: bool,
+    // Applied to every single channel reading on the worker thread, the result ends up in `Reading::engineering`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub transform: Option<Transform>,
+    // Keep the raw response of the last transfer in `Reading::frame`, e.g. all 0xFF points at wiring rather than decoding.
+    pub debug_frames: bool,
+    // Clamp calibrated readings into `[0, v_ref]`, e.g. when offsets must not produce values above full scale.
+    pub clamp_to_ref: bool,
+    // Measure the actual reference through a channel wired to a known voltage before every other reading,
+    // which corrects supply drift when `voltage_ref` is `Vref::EqualToVdd`. The measured reference then replaces
+    // the configured one, including any `Ready::set_vref`, for scaling and `clamp_to_ref`.
+    pub supply_monitor: Option<SupplyMonitor>,
+    // Periodically read a channel wired to a known voltage, reporting drift through `take_self_test_failure` on
+    // `Ready`, `BlockingMcp3008` or `AsyncSession`; the test runs right before a reading once `interval` has passed.
+    pub self_test: Option<SelfTest>,
+    // Where conversions come from, `Session::with_backend` and friends override it.
+    pub backend: Backend,
+}
+
+impl Params {
+    pub fn builder() -> ParamsBuilder {
+        ParamsBuilder::new()
+    }
+
+    // Spi0 with CE0, the usual wiring
+    pub fn new(voltage_drain: Vdd, voltage_ref: Vref) -> Params {
+        Params::with_spi(voltage_drain, voltage_ref, Bus::Spi0, SlaveSelect::Ss0)
+    }
+
+    pub fn with_spi(voltage_drain: Vdd, voltage_ref: Vref, bus: Bus, slave_select: SlaveSelect) -> Params {
+        Params {
+            voltage_drain,
+            voltage_ref,
+            bus,
+            slave_select,
+            clock_hz: None,
+            mode: Mode::Mode0,
+            chip: Chip::Mcp3008,
+            framing: Framing::Standard,
+            oversample: 1,
+            filter: FilterMode::None,
+            sample_interval: None,
+            channel_switch_delay: None,
+            max_retries: 0,
+            retry_delay: Duration::from_millis(1),
+            watch_interval: Duration::from_millis(10),
+            watch_hysteresis: Volt(0.01),
+            calibration: [Calibration::default(); 8],
+            thread_name: None,
+            stack_size: None,
+            check_null_bit: false,
+            transform: None,
+            debug_frames: false,
+            clamp_to_ref: false,
+            supply_monitor: None,
+            self_test: None,
+            backend: Backend::Spi,
+        }
+    }
+}
+
+// starts from 3V3 drain, Vref tied to Vdd, Spi0 with CE0 and Mode0, everything else as in `Params::with_spi`
+#[derive(Clone, Debug)]
+pub struct ParamsBuilder {
+    params: Params,
+}
+
+impl Default for ParamsBuilder {
+    fn default() -> ParamsBuilder {
+        ParamsBuilder::new()
+    }
+}
+
+impl ParamsBuilder {
+    pub fn new() -> ParamsBuilder {
+        ParamsBuilder { params: Params::new(Vdd::Positive3v3, Vref::EqualToVdd), }
+    }
+
+    pub fn voltage_drain(mut self, voltage_drain: Vdd) -> ParamsBuilder {
+        self.params.voltage_drain = voltage_drain;
+        self
+    }
+
+    pub fn voltage_ref(mut self, voltage_ref: Vref) -> ParamsBuilder {
+        self.params.voltage_ref = voltage_ref;
+        self
+    }
+
+    pub fn spi(mut self, bus: Bus, slave_select: SlaveSelect) -> ParamsBuilder {
+        self.params.bus = bus;
+        self.params.slave_select = slave_select;
+        self
+    }
+
+    pub fn clock_hz(mut self, clock_hz: u32) -> ParamsBuilder {
+        self.params.clock_hz = Some(clock_hz);
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> ParamsBuilder {
+        self.params.mode = mode;
+        self
+    }
+
+    pub fn chip(mut self, chip: Chip) -> ParamsBuilder {
+        self.params.chip = chip;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> ParamsBuilder {
+        self.params.framing = framing;
+        self
+    }
+
+    pub fn oversample(mut self, oversample: u16) -> ParamsBuilder {
+        self.params.oversample = oversample;
+        self
+    }
+
+    pub fn filter(mut self, filter: FilterMode) -> ParamsBuilder {
+        self.params.filter = filter;
+        self
+    }
+
+    pub fn sample_interval(mut self, sample_interval: Duration) -> ParamsBuilder {
+        self.params.sample_interval = Some(sample_interval);
+        self
+    }
+
+    pub fn channel_switch_delay(mut self, channel_switch_delay: Duration) -> ParamsBuilder {
+        self.params.channel_switch_delay = Some(channel_switch_delay);
+        self
+    }
+
+    pub fn retries(mut self, max_retries: u8, retry_delay: Duration) -> ParamsBuilder {
+        self.params.max_retries = max_retries;
+        self.params.retry_delay = retry_delay;
+        self
+    }
+
+    pub fn watch_interval(mut self, watch_interval: Duration) -> ParamsBuilder {
+        self.params.watch_interval = watch_interval;
+        self
+    }
+
+    pub fn watch_hysteresis(mut self, watch_hysteresis: Volt) -> ParamsBuilder {
+        self.params.watch_hysteresis = watch_hysteresis;
+        self
+    }
+
+    pub fn calibration(mut self, channel: Channel, calibration: Calibration) -> ParamsBuilder {
+        self.params.calibration[u8::from(channel) as usize] = calibration;
+        self
+    }
+
+    pub fn thread_name<S>(mut self, thread_name: S) -> ParamsBuilder where S: Into<String> {
+        self.params.thread_name = Some(thread_name.into());
+        self
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> ParamsBuilder {
+        self.params.stack_size = Some(stack_size);
+        self
+    }
+
+    pub fn check_null_bit(mut self, check_null_bit: bool) -> ParamsBuilder {
+        self.params.check_null_bit = check_null_bit;
+        self
+    }
+
+    pub fn transform<F>(mut self, transform: F) -> ParamsBuilder where F: Fn(Volt) -> f64 + Send + Sync + 'static {
+        self.params.transform = Some(Transform::new(transform));
+        self
+    }
+
+    pub fn debug_frames(mut self, debug_frames: bool) -> ParamsBuilder {
+        self.params.debug_frames = debug_frames;
+        self
+    }
+
+    pub fn clamp_to_ref(mut self, clamp_to_ref: bool) -> ParamsBuilder {
+        self.params.clamp_to_ref = clamp_to_ref;
+        self
+    }
+
+    pub fn supply_monitor(mut self, channel: Channel, voltage: Volt) -> ParamsBuilder {
+        self.params.supply_monitor = Some(SupplyMonitor { channel, voltage, });
+        self
+    }
+
+    pub fn self_test(mut self, channel: Channel, expected: Volt, tolerance: Volt, interval: Duration) -> ParamsBuilder {
+        self.params.self_test = Some(SelfTest { channel, expected, tolerance, interval, });
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> ParamsBuilder {
+        self.params.backend = backend;
+        self
+    }
+
+    // fails the same way `Session::new` would, e.g. with `Error::InvalidVref` for a Vref above Vdd
+    pub fn build(self) -> Result<Params, Error> {
+        Config::new(&self.params)?;
+        Ok(self.params)
+    }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Vdd {
     Positive3v3,
     Positive5v,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Vref {
     EqualToVdd,
-    Other { voltage: Volt, },
+    Other {
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        voltage: Volt,
+    },
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum Channel {
-    Ch0,
-    Ch1,
-    Ch2,
-    Ch3,
-    Ch4,
-    Ch5,
-    Ch6,
-    Ch7,
+// maps a calibrated voltage to a physical quantity, e.g. a thermistor curve to degrees
+#[derive(Clone)]
+pub struct Transform(pub Arc<dyn Fn(Volt) -> f64 + Send + Sync>);
+
+impl Transform {
+    pub fn new<F>(transform: F) -> Transform where F: Fn(Volt) -> f64 + Send + Sync + 'static {
+        Transform(Arc::new(transform))
+    }
 }
 
-#[derive(Debug)]
-pub enum Error {
-    SpiThreadSpawn(io::Error),
-    SpiThreadLost,
-    SpiInitialize(rppal::spi::Error),
-    SpiTransferSegments(rppal::spi::Error),
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Transform(..)")
+    }
 }
 
-impl Session {
-    pub fn new(params: &Params) -> Result<Self, Error> {
-        let hz = match params.voltage_drain {
-            Vdd::Positive3v3 =>
-                1_350_000,
-            Vdd::Positive5v =>
-                3_600_000,
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Backend {
+    Spi,
+    // no hardware involved: each channel (indexed by number) follows its waveform from the moment the worker starts,
+    // converted against the initial v_ref through the configured framing, pseudo-differential reads included,
+    // see `mock::SimulatedSpi`
+    Simulated { waveforms: Box<[Waveform; 8]>, },
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Waveform {
+    Constant {
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        voltage: Volt,
+    },
+    // sawtooth going from `from` to `to` once per `period`
+    Ramp {
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        from: Volt,
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        to: Volt,
+        period: Duration,
+    },
+    Sine {
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        offset: Volt,
+        #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+        amplitude: Volt,
+        period: Duration,
+    },
+}
+
+impl Waveform {
+    // a zero period holds the waveform at its start
+    pub fn voltage_at(&self, elapsed: Duration) -> Volt {
+        let phase = |period: Duration| if period.is_zero() {
+            0.0
+        } else {
+            (elapsed.as_secs_f64() / period.as_secs_f64()).fract()
         };
-        let v_ref = match params.voltage_ref {
-            Vref::EqualToVdd =>
-                match params.voltage_drain {
-                    Vdd::Positive3v3 =>
-                        Volt(3.3),
-                    Vdd::Positive5v =>
-                        Volt(5.0),
-                },
-            Vref::Other { voltage, } =>
+        match *self {
+            Waveform::Constant { voltage, } =>
                 voltage,
-        };
+            Waveform::Ramp { from, to, period, } =>
+                Volt(from.0 + (to.0 - from.0) * phase(period)),
+            Waveform::Sine { offset, amplitude, period, } =>
+                Volt(offset.0 + amplitude.0 * (2.0 * PI * phase(period)).sin()),
+        }
+    }
+}
 
-        let (request_tx, request_rx) = mpsc::sync_channel(0);
-        let (event_tx, event_rx) = mpsc::sync_channel(0);
+// e.g. a precision shunt reference on a spare channel, its reading gives away the real v_ref
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SupplyMonitor {
+    pub channel: Channel,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+    pub voltage: Volt,
+}
 
-        let _builder = thread::Builder::new()
-            .name("Mcp3008 spi".into())
-            .spawn(move || spi_worker(request_rx, event_tx, hz, v_ref))
-            .map_err(Error::SpiThreadSpawn)?;
+// checked before the next read once `interval` has passed since the previous check
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SelfTest {
+    pub channel: Channel,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+    pub expected: Volt,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impl::volt"))]
+    pub tolerance: Volt,
+    pub interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SelfTestFailure {
+    pub measured: Volt,
+    pub expected: Volt,
+}
+
+// value = raw_volts * gain + offset
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    pub gain: f64,
+    pub offset: f64,
+}
 
-        Ok(Session::Initializing(Initializing {
-            inner: Inner { request_tx, event_rx, },
-        }))
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration { gain: 1.0, offset: 0.0, }
     }
 }
 
-// Initializing
+// lengths of 0 and 1 both mean a single raw sample, a median of an even length averages the middle two
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FilterMode {
+    None,
+    Mean(u16),
+    Median(u16),
+}
 
-pub struct Initializing {
-    inner: Inner,
+// MCP3004 is protocol compatible with MCP3008 but only has CH0..CH3,
+// MCP3204/MCP3208 are their 12-bit counterparts, MCP3002 is a two channel part with a 16 clock frame
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Chip {
+    Mcp3002,
+    Mcp3004,
+    Mcp3008,
+    Mcp3204,
+    Mcp3208,
 }
 
-impl From<Initializing> for Session {
-    fn from(state: Initializing) -> Session {
-        Session::Initializing(state)
+impl Chip {
+    pub fn supports(&self, channel: Channel) -> bool {
+        match (self, channel) {
+            (Chip::Mcp3002, Channel::Ch0) |
+            (Chip::Mcp3002, Channel::Ch1) |
+            (Chip::Mcp3004, Channel::Ch0) |
+            (Chip::Mcp3004, Channel::Ch1) |
+            (Chip::Mcp3004, Channel::Ch2) |
+            (Chip::Mcp3004, Channel::Ch3) |
+            (Chip::Mcp3204, Channel::Ch0) |
+            (Chip::Mcp3204, Channel::Ch1) |
+            (Chip::Mcp3204, Channel::Ch2) |
+            (Chip::Mcp3204, Channel::Ch3) =>
+                true,
+            (Chip::Mcp3002, _) |
+            (Chip::Mcp3004, _) |
+            (Chip::Mcp3204, _) =>
+                false,
+            (Chip::Mcp3008, _) |
+            (Chip::Mcp3208, _) =>
+                true,
+        }
     }
-}
 
-impl Initializing {
-    pub fn probe(self) -> Result<InitializingOp, Error> {
-        match self.inner.event_rx.try_recv() {
-            Ok(Event::SpiInitialized) =>
-                Ok(InitializingOp::Ready(Ready { inner: self.inner, })),
-            Ok(Event::ChannelRead { .. }) =>
-                unreachable!(),
-            Ok(Event::Error(error)) =>
-                Err(error),
-            Err(mpsc::TryRecvError::Empty) =>
-                Ok(InitializingOp::Idle(self)),
-            Err(mpsc::TryRecvError::Disconnected) =>
-                Err(Error::SpiThreadLost),
+    pub fn resolution_bits(&self) -> u8 {
+        match self {
+            Chip::Mcp3002 | Chip::Mcp3004 | Chip::Mcp3008 =>
+                10,
+            Chip::Mcp3204 | Chip::Mcp3208 =>
+                12,
+        }
+    }
+
+    // the start bit is placed so that the last result bit is the last bit of the frame,
+    // which for 12-bit parts means shifting the whole command two clocks earlier
+    pub fn frame_layout(&self) -> FrameLayout {
+        match self {
+            // start, SGL/DIFF, ODD/SIGN as the channel and MSBF set, as the chip would otherwise repeat the result LSB first
+            Chip::Mcp3002 =>
+                FrameLayout {
+                    command: [0b01001000, 0b00000000, 0b00000000],
+                    single_ended_bit: 21,
+                    channel_shift: 20,
+                    result_shift: 8,
+                    result_mask: 0b0000_0011_1111_1111,
+                },
+            Chip::Mcp3004 | Chip::Mcp3008 =>
+                FrameLayout {
+                    command: [0b00000001, 0b00000000, 0b00000000],
+                    single_ended_bit: 15,
+                    channel_shift: 12,
+                    result_shift: 0,
+                    result_mask: 0b0000_0011_1111_1111,
+                },
+            Chip::Mcp3204 | Chip::Mcp3208 =>
+                FrameLayout {
+                    command: [0b00000100, 0b00000000, 0b00000000],
+                    single_ended_bit: 17,
+                    channel_shift: 14,
+                    result_shift: 0,
+                    result_mask: 0b0000_1111_1111_1111,
+                },
         }
     }
 
+    fn ensure_supported(&self, channel: Channel) -> Result<(), Error> {
+        if self.supports(channel) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedChannel { channel, chip: *self, })
+        }
+    }
 }
 
-pub enum InitializingOp {
-    Idle(Initializing),
-    Ready(Ready),
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Framing {
+    Standard,
+    Custom(FrameLayout),
 }
 
-// Ready
+// bit positions count within the frame taken as one big-endian 24-bit word, bit 0 being the last one clocked;
+// a two byte MCP3002 frame occupies bits 23..8
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameLayout {
+    // constant part of the command, i.e. the start bit and whatever else the chip or a bridge expects around it
+    pub command: [u8; 3],
+    // SGL/DIFF selects single-ended (1) or pseudo-differential (0) input
+    pub single_ended_bit: u8,
+    // lowest bit of the channel index
+    pub channel_shift: u8,
+    pub result_shift: u8,
+    // applied after `result_shift`, its width has to match `Chip::resolution_bits` as readings are scaled by that
+    pub result_mask: u16,
+}
 
-pub struct Ready {
-    inner: Inner,
+impl FrameLayout {
+    fn command(&self, index: u8, single_ended: bool) -> [u8; 3] {
+        let sgl_diff = if single_ended { 1 } else { 0 };
+        let word = u32::from_be_bytes([0, self.command[0], self.command[1], self.command[2]])
+            | sgl_diff << self.single_ended_bit
+            | (index as u32) << self.channel_shift;
+        let [_, byte0, byte1, byte2] = word.to_be_bytes();
+        [byte0, byte1, byte2]
+    }
+
+    // the chip always drives the bit right before the result low
+    fn null_bit_set(&self, frame: &[u8; 3]) -> bool {
+        Self::word(frame) & 1 << (self.result_shift as u32 + self.result_width()) != 0
+    }
+
+    fn decode(&self, frame: &[u8; 3]) -> u16 {
+        (Self::word(frame) >> self.result_shift) as u16 & self.result_mask
+    }
+
+    fn result_width(&self) -> u32 {
+        16 - self.result_mask.leading_zeros()
+    }
+
+    // clocking stops after the byte holding the last result bit, the rest of the 3-byte buffers is left alone
+    fn frame_len(&self) -> usize {
+        3 - (self.result_shift / 8) as usize
+    }
+
+    fn word(frame: &[u8; 3]) -> u32 {
+        u32::from_be_bytes([0, frame[0], frame[1], frame[2]])
+    }
+
+    // every field has to fit into the 24 bits of the frame, the channel index taking three
+    fn is_valid(&self) -> bool {
+        self.single_ended_bit < 24
+            && self.channel_shift <= 21
+            && self.result_mask != 0
+            && (self.result_shift as u32) + self.result_width() < 24
+    }
 }
 
-impl From<Ready> for Session {
-    fn from(state: Ready) -> Session {
-        Session::Ready(state)
+// ordered by channel number, so sorted channels and ordered maps follow the pinout
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Channel {
+    Ch0,
+    Ch1,
+    Ch2,
+    Ch3,
+    Ch4,
+    Ch5,
+    Ch6,
+    Ch7,
+}
+
+impl Channel {
+    // in channel number order
+    pub const ALL: [Channel; 8] = [
+        Channel::Ch0,
+        Channel::Ch1,
+        Channel::Ch2,
+        Channel::Ch3,
+        Channel::Ch4,
+        Channel::Ch5,
+        Channel::Ch6,
+        Channel::Ch7,
+    ];
+
+    pub fn all() -> impl Iterator<Item = Channel> {
+        Channel::ALL.iter().copied()
     }
 }
 
-impl Ready {
-    pub fn probe_channel(self, channel: Channel) -> Probing {
-        Probing {
-            state: ProbingState::Request { channel, },
-            inner: self.inner,
+impl From<Channel> for u8 {
+    fn from(channel: Channel) -> u8 {
+        match channel {
+            Channel::Ch0 => 0,
+            Channel::Ch1 => 1,
+            Channel::Ch2 => 2,
+            Channel::Ch3 => 3,
+            Channel::Ch4 => 4,
+            Channel::Ch5 => 5,
+            Channel::Ch6 => 6,
+            Channel::Ch7 => 7,
         }
     }
 }
 
-// Probing
-
-pub struct Probing {
-    state: ProbingState,
-    inner: Inner,
-}
+impl TryFrom<u8> for Channel {
+    type Error = Error;
 
-impl From<Probing> for Session {
-    fn from(state: Probing) -> Session {
-        Session::Probing(state)
+    fn try_from(index: u8) -> Result<Channel, Error> {
+        match index {
+            0 => Ok(Channel::Ch0),
+            1 => Ok(Channel::Ch1),
+            2 => Ok(Channel::Ch2),
+            3 => Ok(Channel::Ch3),
+            4 => Ok(Channel::Ch4),
+            5 => Ok(Channel::Ch5),
+            6 => Ok(Channel::Ch6),
+            7 => Ok(Channel::Ch7),
+            _ => Err(Error::InvalidChannelIndex { index, }),
+        }
     }
 }
 
-enum ProbingState {
-    Request { channel: Channel, },
-    WaitingReply,
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum DifferentialPair {
+    Ch0PlusCh1Minus,
+    Ch0MinusCh1Plus,
+    Ch2PlusCh3Minus,
+    Ch2MinusCh3Plus,
+    Ch4PlusCh5Minus,
+    Ch4MinusCh5Plus,
+    Ch6PlusCh7Minus,
+    Ch6MinusCh7Plus,
 }
 
-impl Probing {
-    pub fn poll(mut self) -> Result<ProbingOp, Error> {
-        loop {
-            match self.state {
-                ProbingState::Request { channel, } =>
-                    match self.inner.request_tx.try_send(Request::ProbeChannel { channel, }) {
-                        Ok(()) =>
-                            self.state = ProbingState::WaitingReply,
-                        Err(mpsc::TrySendError::Full(..)) =>
-                            return Ok(ProbingOp::Idle(self)),
-                        Err(mpsc::TrySendError::Disconnected(..)) =>
-                            return Err(Error::SpiThreadLost),
-                    },
-                ProbingState::WaitingReply =>
-                    match self.inner.event_rx.try_recv() {
-                        Ok(Event::SpiInitialized) =>
-                            unreachable!(),
-                        Ok(Event::ChannelRead { channel, value, }) =>
-                            return Ok(ProbingOp::Done {
-                                channel,
-                                value,
-                                ready: Ready { inner: self.inner, },
-                            }),
-                        Ok(Event::Error(error)) =>
-                            return Err(error),
-                        Err(mpsc::TryRecvError::Empty) =>
-                            return Ok(ProbingOp::Idle(self)),
-                        Err(mpsc::TryRecvError::Disconnected) =>
-                            return Err(Error::SpiThreadLost),
-                    }
-            }
+impl DifferentialPair {
+    // IN+ channel, which is also what the chip's D2..D0 bits encode and what `ProbingOp::Done` reports
+    pub fn positive(&self) -> Channel {
+        match self {
+            DifferentialPair::Ch0PlusCh1Minus => Channel::Ch0,
+            DifferentialPair::Ch0MinusCh1Plus => Channel::Ch1,
+            DifferentialPair::Ch2PlusCh3Minus => Channel::Ch2,
+            DifferentialPair::Ch2MinusCh3Plus => Channel::Ch3,
+            DifferentialPair::Ch4PlusCh5Minus => Channel::Ch4,
+            DifferentialPair::Ch4MinusCh5Plus => Channel::Ch5,
+            DifferentialPair::Ch6PlusCh7Minus => Channel::Ch6,
+            DifferentialPair::Ch6MinusCh7Plus => Channel::Ch7,
+        }
+    }
+
+    pub fn negative(&self) -> Channel {
+        match self {
+            DifferentialPair::Ch0PlusCh1Minus => Channel::Ch1,
+            DifferentialPair::Ch0MinusCh1Plus => Channel::Ch0,
+            DifferentialPair::Ch2PlusCh3Minus => Channel::Ch3,
+            DifferentialPair::Ch2MinusCh3Plus => Channel::Ch2,
+            DifferentialPair::Ch4PlusCh5Minus => Channel::Ch5,
+            DifferentialPair::Ch4MinusCh5Plus => Channel::Ch4,
+            DifferentialPair::Ch6PlusCh7Minus => Channel::Ch7,
+            DifferentialPair::Ch6MinusCh7Plus => Channel::Ch6,
         }
     }
 }
 
-pub enum ProbingOp {
-    Idle(Probing),
-    Done {
-        channel: Channel,
-        value: Volt,
-        ready: Ready,
-    },
+#[derive(Clone, Copy, Debug)]
+pub struct Reading {
+    pub value: Volt,
+    // `value` before `Params::calibration` is applied
+    pub uncalibrated: Volt,
+    pub raw: u16,
+    // 10 or 12 depending on `Params::chip`
+    pub resolution_bits: u8,
+    // `Params::transform` of `value`, if one is configured
+    pub engineering: Option<f64>,
+    // the last 3-byte response as it came off the wire (MCP3002 only fills two), only with `Params::debug_frames`
+    pub frame: Option<[u8; 3]>,
+    // taken on the worker thread right after the (last oversampled) transfer
+    pub timestamp: Instant,
 }
 
-// inner impl
+impl Reading {
+    // position within the adc range regardless of v_ref, in `[0.0, 1.0]`
+    pub fn fraction(&self) -> f64 {
+        self.raw as f64 / ((1u32 << self.resolution_bits) - 1) as f64
+    }
 
-struct Inner {
-    request_tx: mpsc::SyncSender<Request>,
-    event_rx: mpsc::Receiver<Event>,
+    // `value` rounded to the nearest millivolt, halves away from zero;
+    // saturates at the i32 bounds and maps NaN to 0, which is what an `as` cast from f64 does
+    pub fn as_millivolts_i32(&self) -> i32 {
+        (self.value.0 * 1000.0).round() as i32
+    }
 }
 
-enum Request {
-    ProbeChannel { channel: Channel, },
+#[derive(Debug)]
+pub enum Error {
+    SpiThreadSpawn(io::Error),
+    SpiThreadLost,
+    SpiInitialize(rppal::spi::Error),
+    SpiTransferSegments(rppal::spi::Error),
+    UnsupportedChannel { channel: Channel, chip: Chip, },
+    ProbeTimeout { channel: Channel, },
+    InvalidChannelIndex { index: u8, },
+    InvalidVref { voltage: Volt, },
+    ImplausibleReading { channel: Channel, },
+    InitTimeout,
+    InvalidFraming { layout: FrameLayout, },
 }
 
-enum Event {
-    SpiInitialized,
-    ChannelRead { channel: Channel, value: Volt, },
-    Error(Error),
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SpiThreadSpawn(error) =>
+                write!(f, "failed to spawn spi worker thread: {}", error),
+            Error::SpiThreadLost =>
+                write!(f, "spi worker thread has terminated unexpectedly"),
+            Error::SpiInitialize(error) =>
+                write!(f, "failed to initialize spi peripheral: {}", error),
+            Error::SpiTransferSegments(error) =>
+                write!(f, "spi transfer failed: {}", error),
+            Error::UnsupportedChannel { channel, chip, } =>
+                write!(f, "channel {:?} is not available on {:?}", channel, chip),
+            Error::ProbeTimeout { channel, } =>
+                write!(f, "no reply from spi worker while probing channel {:?}", channel),
+            Error::InvalidChannelIndex { index, } =>
+                write!(f, "there is no channel with index {}", index),
+            Error::InvalidVref { voltage, } =>
+                write!(f, "reference voltage {} V must be positive and not exceed the drain voltage", voltage.0),
+            Error::ImplausibleReading { channel, } =>
+                write!(f, "reading {:?} returned a malformed frame, the chip is probably not responding", channel),
+            Error::InitTimeout =>
+                write!(f, "spi worker did not finish initialization in time"),
+            Error::InvalidFraming { layout, } =>
+                write!(f, "frame layout {:?} does not fit into a 24 clock frame or its result mask does not match the chip resolution", layout),
+        }
+    }
 }
 
-fn spi_worker(request_rx: mpsc::Receiver<Request>, event_tx: mpsc::SyncSender<Event>, hz: u32, v_ref: Volt) {
-    if let Err(error) = spi_worker_loop(request_rx, &event_tx, hz, v_ref) {
-        event_tx.send(Event::Error(error)).ok();
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::SpiThreadSpawn(error) =>
+                Some(error),
+            Error::SpiInitialize(error) |
+            Error::SpiTransferSegments(error) =>
+                Some(error),
+            Error::SpiThreadLost |
+            Error::UnsupportedChannel { .. } |
+            Error::ProbeTimeout { .. } |
+            Error::InvalidChannelIndex { .. } |
+            Error::InvalidVref { .. } |
+            Error::ImplausibleReading { .. } |
+            Error::InitTimeout |
+            Error::InvalidFraming { .. } =>
+                None,
+        }
     }
 }
 
-fn spi_worker_loop(
-    request_rx: mpsc::Receiver<Request>,
-    event_tx: &mpsc::SyncSender<Event>,
-    hz: u32,
+impl Session {
+    // rppal opens a separate spidev device (/dev/spidevB.S) per slave select rather than claiming the whole bus,
+    // so sessions for chips on CE0 and CE1 of one bus run side by side, each on its own worker,
+    // and the kernel serializes their transfers on the shared lines
+    pub fn new(params: &Params) -> Result<Self, Error> {
+        Session::spawn(params, None).map(Session::Initializing)
+    }
+
+    // the worker talks to `backend` instead of opening `params.bus`, e.g. `mock::MockSpi` for host-side tests
+    pub fn with_backend(params: &Params, backend: Box<dyn SpiTransfer + Send>) -> Result<Self, Error> {
+        Session::spawn(params, Some(backend)).map(Session::Initializing)
+    }
+
+    // blocks until the worker has opened the spi peripheral, so initialization errors come back right here
+    pub fn open(params: &Params) -> Result<Ready, Error> {
+        Session::open_within(params, None, None)
+    }
+
+    pub fn open_with_backend(params: &Params, backend: Box<dyn SpiTransfer + Send>) -> Result<Ready, Error> {
+        Session::open_within(params, Some(backend), None)
+    }
+
+    // same as `open`, giving up with `Error::InitTimeout` when opening spi takes longer than `timeout`
+    pub fn open_ready(params: &Params, timeout: Duration) -> Result<Ready, Error> {
+        Session::open_within(params, None, Some(timeout))
+    }
+
+    pub fn open_ready_with_backend(
+        params: &Params,
+        backend: Box<dyn SpiTransfer + Send>,
+        timeout: Duration,
+    )
+        -> Result<Ready, Error>
+    {
+        Session::open_within(params, Some(backend), Some(timeout))
+    }
+
+    fn open_within(
+        params: &Params,
+        backend: Option<Box<dyn SpiTransfer + Send>>,
+        timeout: Option<Duration>,
+    )
+        -> Result<Ready, Error>
+    {
+        let initializing = Session::spawn(params, backend)?;
+        match timeout {
+            Some(timeout) =>
+                initializing.wait(timeout),
+            None =>
+                initializing.recv_initialized(),
+        }
+    }
+
+    fn spawn(params: &Params, backend: Option<Box<dyn SpiTransfer + Send>>) -> Result<Initializing, Error> {
+        let config = Config::new(params)?;
+        let chip = config.chip;
+        let v_dd = config.v_dd;
+        let v_ref = config.v_ref;
+        let sample_rate = config.sample_rate.clone();
+
+        // The typestate keeps at most one request in flight, and the worker only takes the next request after
+        // replying to the previous one. A single slot for events is therefore enough for the worker to never
+        // block on a reply (even a stale one after a timeout), so it can't stall against a pending `try_send`.
+        let (request_tx, request_rx) = mpsc::sync_channel(0);
+        let (event_tx, event_rx) = mpsc::sync_channel(1);
+
+        let thread_name = match params.thread_name {
+            Some(ref thread_name) =>
+                thread_name.clone(),
+            None =>
+                format!("Mcp3008 spi{}.{}", params.bus as u8, params.slave_select as u8),
+        };
+        let mut builder = thread::Builder::new()
+            .name(thread_name);
+        if let Some(stack_size) = params.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let handle = builder
+            .spawn(move || spi_worker(request_rx, event_tx, backend, config))
+            .map_err(Error::SpiThreadSpawn)?;
+
+        Ok(Initializing {
+            inner: Inner {
+                request_tx,
+                event_rx,
+                stale_replies: 0,
+                details: Box::new(Details {
+                    chip,
+                    v_dd,
+                    v_ref,
+                    sample_rate,
+                    spare_channels: Vec::new(),
+                    spare_readings: Vec::new(),
+                    self_test_failure: None,
+                    peeked: RefCell::new(VecDeque::new()),
+                }),
+                worker: Worker { handle: Some(handle), },
+            },
+        })
+    }
+
+    // same as dropping the session, but explicit about waiting for the spi thread to finish
+    pub fn shutdown(self) {
+        match self {
+            Session::Initializing(Initializing { inner, }) |
+            Session::Ready(Ready { inner, }) |
+            Session::Probing(Probing { inner, .. }) |
+            Session::ProbingBatch(ProbingBatch { inner, .. }) |
+            Session::ScanAll(ScanAll { batch: ProbingBatch { inner, .. }, }) |
+            Session::Streaming(Streaming { inner, .. }) |
+            Session::Watching(Watching { inner, .. }) |
+            Session::Debouncing(Debouncing { inner, .. }) =>
+                inner.shutdown(),
+        }
+    }
+
+    // ends whatever the session is doing first: an outstanding probe reply is skipped
+    // and an error which ended a free run is dropped, since the point is to start over
+    pub fn reinitialize(self) -> Result<Initializing, Error> {
+        let ready = match self {
+            Session::Initializing(initializing) =>
+                // the worker is opening spi already
+                return Ok(initializing),
+            Session::Ready(ready) =>
+                ready,
+            Session::Probing(Probing { state, inner, .. }) |
+            Session::ProbingBatch(ProbingBatch { state, inner, }) |
+            Session::ScanAll(ScanAll { batch: ProbingBatch { state, inner, }, }) =>
+                abandon_probe(state, inner),
+            Session::Streaming(streaming) =>
+                recover(streaming.stop())?,
+            Session::Watching(watching) =>
+                recover(watching.stop())?,
+            Session::Debouncing(debouncing) =>
+                recover(debouncing.stop())?,
+        };
+        ready.reinitialize()
+    }
+
+    // false once the spi thread has exited or panicked, for supervisors which restart the session rather than
+    // waiting for `Error::SpiThreadLost` on the next poll
+    pub fn is_worker_alive(&self) -> bool {
+        match self {
+            Session::Initializing(Initializing { inner, }) |
+            Session::Ready(Ready { inner, }) |
+            Session::Probing(Probing { inner, .. }) |
+            Session::ProbingBatch(ProbingBatch { inner, .. }) |
+            Session::ScanAll(ScanAll { batch: ProbingBatch { inner, .. }, }) |
+            Session::Streaming(Streaming { inner, .. }) |
+            Session::Watching(Watching { inner, .. }) |
+            Session::Debouncing(Debouncing { inner, .. }) =>
+                inner.worker.is_alive(),
+        }
+    }
+
+    // joins the spi thread, reporting a worker panic as `Err` with its payload
+    pub fn join(self) -> thread::Result<()> {
+        match self {
+            Session::Initializing(Initializing { inner, }) |
+            Session::Ready(Ready { inner, }) |
+            Session::Probing(Probing { inner, .. }) |
+            Session::ProbingBatch(ProbingBatch { inner, .. }) |
+            Session::ScanAll(ScanAll { batch: ProbingBatch { inner, .. }, }) |
+            Session::Streaming(Streaming { inner, .. }) |
+            Session::Watching(Watching { inner, .. }) |
+            Session::Debouncing(Debouncing { inner, .. }) =>
+                inner.join(),
+        }
+    }
+}
+
+fn abandon_probe(state: ProbingState, mut inner: Inner) -> Ready {
+    if let ProbingState::WaitingReply = state {
+        inner.stale_replies += 1;
+    }
+    Ready { inner, }
+}
+
+fn recover(result: Result<Ready, PollError>) -> Result<Ready, Error> {
+    match result {
+        Ok(ready) | Err(PollError::Recoverable { ready, .. }) =>
+            Ok(ready),
+        Err(PollError::Fatal(error)) =>
+            Err(error),
+    }
+}
+
+fn validate_vref(voltage: Volt, v_dd: Volt) -> Result<Volt, Error> {
+    // written this way to reject NaN as well
+    if voltage.0 > 0.0 && voltage.0 <= v_dd.0 {
+        Ok(voltage)
+    } else {
+        Err(Error::InvalidVref { voltage, })
+    }
+}
+
+// Initializing
+
+pub struct Initializing {
+    inner: Inner,
+}
+
+impl From<Initializing> for Session {
+    fn from(state: Initializing) -> Session {
+        Session::Initializing(state)
+    }
+}
+
+impl Initializing {
+    pub fn probe(self) -> Result<InitializingOp, Error> {
+        loop {
+            match self.inner.event_rx.try_recv() {
+                Ok(Event::SpiInitialized) =>
+                    return Ok(InitializingOp::Ready(Ready { inner: self.inner, })),
+                Ok(event @ Event::ChannelRead { .. }) | Ok(event @ Event::ChannelsRead { .. }) =>
+                    skip_event(&event),
+                Ok(Event::Error(error)) =>
+                    return Err(error),
+                Err(mpsc::TryRecvError::Empty) =>
+                    return Ok(InitializingOp::Idle(self)),
+                Err(mpsc::TryRecvError::Disconnected) =>
+                    return Err(Error::SpiThreadLost),
+            }
+        }
+    }
+
+    // a timeout too large to form a deadline, like `Duration::MAX`, waits forever
+    pub fn wait(mut self, timeout: Duration) -> Result<Ready, Error> {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) =>
+                deadline,
+            None =>
+                return self.recv_initialized(),
+        };
+        loop {
+            match self.inner.event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::SpiInitialized) =>
+                    return Ok(Ready { inner: self.inner, }),
+                Ok(event @ Event::ChannelRead { .. }) | Ok(event @ Event::ChannelsRead { .. }) =>
+                    skip_event(&event),
+                Ok(Event::Error(error)) =>
+                    return Err(error),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    // detached rather than joined: a worker stuck opening spi exits by itself once it notices
+                    // the session is gone, and joining it here would defeat the timeout
+                    self.inner.worker.handle.take();
+                    return Err(Error::InitTimeout);
+                },
+                Err(mpsc::RecvTimeoutError::Disconnected) =>
+                    return Err(Error::SpiThreadLost),
+            }
+        }
+    }
+
+    fn recv_initialized(self) -> Result<Ready, Error> {
+        loop {
+            match self.inner.event_rx.recv() {
+                Ok(Event::SpiInitialized) =>
+                    return Ok(Ready { inner: self.inner, }),
+                Ok(event @ Event::ChannelRead { .. }) | Ok(event @ Event::ChannelsRead { .. }) =>
+                    skip_event(&event),
+                Ok(Event::Error(error)) =>
+                    return Err(error),
+                Err(mpsc::RecvError) =>
+                    return Err(Error::SpiThreadLost),
+            }
+        }
+    }
+}
+
+pub enum InitializingOp {
+    Idle(Initializing),
+    Ready(Ready),
+}
+
+// Ready
+
+pub struct Ready {
+    inner: Inner,
+}
+
+impl From<Ready> for Session {
+    fn from(state: Ready) -> Session {
+        Session::Ready(state)
+    }
+}
+
+impl Ready {
+    pub fn probe_channel(self, channel: Channel) -> Result<Probing, Error> {
+        self.inner.ensure_supported(channel)?;
+        Ok(Probing {
+            channel,
+            state: ProbingState::Request { request: Request::ProbeChannel { channel, }, },
+            inner: self.inner,
+        })
+    }
+
+    pub fn probe_differential(self, pair: DifferentialPair) -> Result<Probing, Error> {
+        self.inner.ensure_supported(pair.positive())?;
+        self.inner.ensure_supported(pair.negative())?;
+        Ok(Probing {
+            channel: pair.positive(),
+            state: ProbingState::Request { request: Request::ProbeDifferential { pair, }, },
+            inner: self.inner,
+        })
+    }
+
+    pub fn probe_channels(self, channels: &[Channel]) -> Result<ProbingBatch, Error> {
+        for &channel in channels {
+            self.inner.ensure_supported(channel)?;
+        }
+        Ok(self.probe_batch(channels.iter().copied()))
+    }
+
+    // every channel the chip has, the others are left out of the batch
+    pub fn read_all(self) -> ScanAll {
+        let chip = self.inner.details.chip;
+        ScanAll { batch: self.probe_batch(Channel::all().filter(|&channel| chip.supports(channel))), }
+    }
+
+    fn probe_batch<I>(mut self, channels: I) -> ProbingBatch where I: Iterator<Item = Channel> {
+        let mut batch_channels = mem::take(&mut self.inner.details.spare_channels);
+        batch_channels.clear();
+        batch_channels.extend(channels);
+        let readings = mem::take(&mut self.inner.details.spare_readings);
+        ProbingBatch {
+            state: ProbingState::Request { request: Request::ProbeChannels { channels: batch_channels, readings, }, },
+            inner: self.inner,
+        }
+    }
+
+    // performs the whole request/reply roundtrip on the caller's thread
+    pub fn read_blocking(&mut self, channel: Channel) -> Result<Volt, Error> {
+        let reading = self.read_blocking_reading(channel)?;
+        Ok(reading.value)
+    }
+
+    fn read_blocking_reading(&mut self, channel: Channel) -> Result<Reading, Error> {
+        self.inner.ensure_supported(channel)?;
+        self.inner.send_request(Request::ProbeChannel { channel, })?;
+        loop {
+            match self.inner.recv_event() {
+                Ok(Event::ChannelRead { reading, .. }) =>
+                    return Ok(reading),
+                Ok(event @ Event::SpiInitialized) | Ok(event @ Event::ChannelsRead { .. }) =>
+                    skip_event(&event),
+                Ok(Event::Error(error)) =>
+                    return Err(error),
+                Err(mpsc::RecvError) =>
+                    return Err(Error::SpiThreadLost),
+            }
+        }
+    }
+
+    // blocking reads every `period`; transfer errors are yielded and sampling goes on, while losing the worker
+    // or an unsupported channel ends the iteration; dropping it closes the session
+    pub fn into_iter_channel(self, channel: Channel, period: Duration) -> impl Iterator<Item = Result<Volt, Error>> {
+        ChannelIter { ready: Some(self), channel, period, started: false, }
+    }
+
+    // the worker free-runs on the channel, samples which do not fit into `capacity` are skipped
+    pub fn stream_channel(mut self, channel: Channel, capacity: usize) -> Result<Streaming, Error> {
+        self.inner.ensure_supported(channel)?;
+        let (samples_tx, samples_rx) = mpsc::sync_channel(capacity);
+        self.inner.send_request(Request::StreamChannel { channel, samples_tx, })?;
+        Ok(Streaming { samples_rx, inner: self.inner, })
+    }
+
+    // samples every `Params::watch_interval` and only reports when the reading leaves or reenters `[low, high]`
+    pub fn watch(mut self, channel: Channel, low: Volt, high: Volt) -> Result<Watching, Error> {
+        self.inner.ensure_supported(channel)?;
+        // transitions are rare, so an unbounded channel never lets the worker block on a slow consumer
+        let (transitions_tx, transitions_rx) = mpsc::channel();
+        self.inner.send_request(Request::Watch { channel, low, high, transitions_tx, })?;
+        Ok(Watching { transitions_rx, inner: self.inner, })
+    }
+
+    // treats the channel as a digital input: it reads high above `threshold + hysteresis / 2` and low below
+    // `threshold - hysteresis / 2`, and a level is only reported once it has held for `stable_for`;
+    // sampled every `Params::watch_interval`, the first confirmed level is reported as well
+    pub fn debounce(
+        mut self,
+        channel: Channel,
+        threshold: Volt,
+        hysteresis: Volt,
+        stable_for: Duration,
+    )
+        -> Result<Debouncing, Error>
+    {
+        self.inner.ensure_supported(channel)?;
+        let (levels_tx, levels_rx) = mpsc::channel();
+        self.inner.send_request(Request::Debounce { channel, threshold, hysteresis, stable_for, levels_tx, })?;
+        Ok(Debouncing { levels_rx, inner: self.inner, })
+    }
+
+    // spi transfers per second actually achieved by the worker, oversampled transfers are counted individually
+    pub fn sample_rate(&self) -> f64 {
+        self.inner.details.sample_rate.hz()
+    }
+
+    // affects every conversion requested afterwards, except while a `Params::supply_monitor` measures the reference
+    pub fn set_vref(mut self, voltage: Volt) -> Result<Ready, Error> {
+        let voltage = validate_vref(voltage, self.inner.details.v_dd)?;
+        self.inner.send_request(Request::SetVref { voltage, })?;
+        self.inner.details.v_ref = voltage;
+        Ok(self)
+    }
+
+    // `observer` is called on the worker thread right after every completed reading, all states included;
+    // it holds up sampling while it runs, so it must not block; a later call replaces the previous observer
+    pub fn with_observer<F>(mut self, observer: F) -> Result<Ready, Error> where F: Fn(Channel, Volt) + Send + 'static {
+        self.inner.send_request(Request::SetObserver { observer: Box::new(observer), })?;
+        Ok(self)
+    }
+
+    // the worker drops its spi handle and reopens it from the stored `Params`, e.g. after a hotplug or a brownout;
+    // a backend given to `Session::with_backend` can't be reopened and is kept as it is
+    pub fn reinitialize(mut self) -> Result<Initializing, Error> {
+        self.inner.send_request(Request::Reinitialize)?;
+        Ok(Initializing { inner: self.inner, })
+    }
+
+    // the reference voltage conversions currently use, after defaulting to Vdd and any `set_vref`;
+    // with a `Params::supply_monitor` this is only the nominal value, readings are scaled by the measured one
+    pub fn v_ref(&self) -> Volt {
+        self.inner.details.v_ref
+    }
+
+    pub fn is_worker_alive(&self) -> bool {
+        self.inner.worker.is_alive()
+    }
+
+    // the latest failed `Params::self_test` since the previous call, earlier ones are overwritten
+    pub fn take_self_test_failure(&mut self) -> Option<SelfTestFailure> {
+        self.inner.details.self_test_failure.take()
+    }
+
+    // voltage of a single count, i.e. the quantization step, derived from the nominal `v_ref`
+    pub fn lsb_volts(&self) -> Volt {
+        Volt(self.inner.details.v_ref.0 / (1u32 << self.inner.details.chip.resolution_bits()) as f64)
+    }
+}
+
+struct ChannelIter {
+    ready: Option<Ready>,
+    channel: Channel,
+    period: Duration,
+    started: bool,
+}
+
+impl Iterator for ChannelIter {
+    type Item = Result<Volt, Error>;
+
+    fn next(&mut self) -> Option<Result<Volt, Error>> {
+        let ready = self.ready.as_mut()?;
+        if self.started {
+            thread::sleep(self.period);
+        }
+        self.started = true;
+        match ready.read_blocking(self.channel) {
+            Ok(value) =>
+                Some(Ok(value)),
+            Err(error @ Error::SpiThreadLost) | Err(error @ Error::UnsupportedChannel { .. }) => {
+                self.ready = None;
+                Some(Err(error))
+            },
+            Err(error) =>
+                Some(Err(error)),
+        }
+    }
+}
+
+// Probing
+
+pub struct Probing {
+    channel: Channel,
+    state: ProbingState,
+    inner: Inner,
+}
+
+impl From<Probing> for Session {
+    fn from(state: Probing) -> Session {
+        Session::Probing(state)
+    }
+}
+
+enum ProbingState {
+    Request { request: Request, },
+    WaitingReply,
+}
+
+impl Probing {
+    // true once `poll` is going to return something other than `ProbingOp::Idle`, without advancing the state,
+    // e.g. for a "sampling..." indicator; false until `poll` has handed the request to the worker
+    pub fn is_ready(&self) -> bool {
+        match self.state {
+            ProbingState::Request { .. } =>
+                false,
+            ProbingState::WaitingReply =>
+                self.inner.peek_reply(),
+        }
+    }
+
+    pub fn poll(mut self) -> Result<ProbingOp, PollError> {
+        loop {
+            match self.state {
+                ProbingState::Request { request, } =>
+                    match self.inner.request_tx.try_send(request) {
+                        Ok(()) =>
+                            self.state = ProbingState::WaitingReply,
+                        Err(mpsc::TrySendError::Full(request)) => {
+                            self.state = ProbingState::Request { request, };
+                            return Ok(ProbingOp::Idle(self));
+                        },
+                        Err(mpsc::TrySendError::Disconnected(..)) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    },
+                ProbingState::WaitingReply =>
+                    match self.inner.try_recv_event() {
+                        Ok(Event::ChannelRead { channel, reading, transfer_duration, .. }) =>
+                            return Ok(ProbingOp::Done {
+                                channel,
+                                reading,
+                                transfer_duration,
+                                ready: Ready { inner: self.inner, },
+                            }),
+                        Ok(event @ Event::SpiInitialized) | Ok(event @ Event::ChannelsRead { .. }) =>
+                            skip_event(&event),
+                        Ok(Event::Error(error)) =>
+                            return Err(PollError::Recoverable { error, ready: Ready { inner: self.inner, }, }),
+                        Err(mpsc::TryRecvError::Empty) =>
+                            return Ok(ProbingOp::Idle(self)),
+                        Err(mpsc::TryRecvError::Disconnected) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    }
+            }
+        }
+    }
+
+    pub fn poll_deadline(self, deadline: Instant) -> Result<ProbingOp, PollError> {
+        match self.poll() {
+            Ok(ProbingOp::Idle(probing)) if Instant::now() >= deadline => {
+                let Probing { channel, state, mut inner, } = probing;
+                if let ProbingState::WaitingReply = state {
+                    // the worker has already taken the request, so its reply is going to arrive later
+                    inner.stale_replies += 1;
+                }
+                Err(PollError::Recoverable {
+                    error: Error::ProbeTimeout { channel, },
+                    ready: Ready { inner, },
+                })
+            },
+            other =>
+                other,
+        }
+    }
+}
+
+// transfer errors leave the worker running, only losing the worker itself is fatal
+pub enum PollError {
+    Fatal(Error),
+    // the session survived the failure and can be reused
+    Recoverable { error: Error, ready: Ready, },
+}
+
+pub enum ProbingOp {
+    Idle(Probing),
+    Done {
+        channel: Channel,
+        reading: Reading,
+        // summed over every transfer behind `reading`: oversampling, filtering, retries and the supply monitor
+        transfer_duration: Duration,
+        ready: Ready,
+    },
+}
+
+// ProbingBatch
+
+pub struct ProbingBatch {
+    state: ProbingState,
+    inner: Inner,
+}
+
+impl From<ProbingBatch> for Session {
+    fn from(state: ProbingBatch) -> Session {
+        Session::ProbingBatch(state)
+    }
+}
+
+impl ProbingBatch {
+    // same as `Probing::is_ready`
+    pub fn is_ready(&self) -> bool {
+        match self.state {
+            ProbingState::Request { .. } =>
+                false,
+            ProbingState::WaitingReply =>
+                self.inner.peek_reply(),
+        }
+    }
+
+    pub fn poll(mut self) -> Result<ProbingBatchOp, PollError> {
+        loop {
+            match self.state {
+                ProbingState::Request { request, } =>
+                    match self.inner.request_tx.try_send(request) {
+                        Ok(()) =>
+                            self.state = ProbingState::WaitingReply,
+                        Err(mpsc::TrySendError::Full(request)) => {
+                            self.state = ProbingState::Request { request, };
+                            return Ok(ProbingBatchOp::Idle(self));
+                        },
+                        Err(mpsc::TrySendError::Disconnected(..)) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    },
+                ProbingState::WaitingReply =>
+                    match self.inner.try_recv_event() {
+                        Ok(event @ Event::SpiInitialized) | Ok(event @ Event::ChannelRead { .. }) =>
+                            skip_event(&event),
+                        Ok(Event::ChannelsRead { channels, readings, .. }) => {
+                            self.inner.details.spare_channels = channels;
+                            return Ok(ProbingBatchOp::Done {
+                                readings,
+                                ready: Ready { inner: self.inner, },
+                            });
+                        },
+                        Ok(Event::Error(error)) =>
+                            return Err(PollError::Recoverable { error, ready: Ready { inner: self.inner, }, }),
+                        Err(mpsc::TryRecvError::Empty) =>
+                            return Ok(ProbingBatchOp::Idle(self)),
+                        Err(mpsc::TryRecvError::Disconnected) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    }
+            }
+        }
+    }
+
+    // copies up to `readings.len()` results into `readings` and keeps the vector for the next batch
+    pub fn poll_into(self, readings: &mut [(Channel, Volt)]) -> Result<ProbingBatchIntoOp, PollError> {
+        match self.poll()? {
+            ProbingBatchOp::Idle(batch) =>
+                Ok(ProbingBatchIntoOp::Idle(batch)),
+            ProbingBatchOp::Done { readings: batch_readings, mut ready, } => {
+                let written = batch_readings.len().min(readings.len());
+                readings[.. written].copy_from_slice(&batch_readings[.. written]);
+                ready.inner.details.spare_readings = batch_readings;
+                Ok(ProbingBatchIntoOp::Done { written, ready, })
+            },
+        }
+    }
+}
+
+pub enum ProbingBatchOp {
+    Idle(ProbingBatch),
+    Done {
+        readings: Vec<(Channel, Volt)>,
+        ready: Ready,
+    },
+}
+
+pub enum ProbingBatchIntoOp {
+    Idle(ProbingBatch),
+    Done {
+        written: usize,
+        ready: Ready,
+    },
+}
+
+// ScanAll
+
+pub struct ScanAll {
+    batch: ProbingBatch,
+}
+
+impl From<ScanAll> for Session {
+    fn from(state: ScanAll) -> Session {
+        Session::ScanAll(state)
+    }
+}
+
+impl ScanAll {
+    pub fn is_ready(&self) -> bool {
+        self.batch.is_ready()
+    }
+
+    pub fn poll(self) -> Result<ScanAllOp, PollError> {
+        match self.batch.poll()? {
+            ProbingBatchOp::Idle(batch) =>
+                Ok(ScanAllOp::Idle(ScanAll { batch, })),
+            ProbingBatchOp::Done { readings, ready, } => {
+                let mut values = [Volt(0.0); 8];
+                for (channel, value) in readings {
+                    values[u8::from(channel) as usize] = value;
+                }
+                Ok(ScanAllOp::Done { values, ready, })
+            },
+        }
+    }
+
+    // same as `poll`, but writes into `values` (indexed by channel number) without allocating,
+    // leaving the entries of channels the chip lacks alone
+    pub fn poll_into(self, values: &mut [Volt; 8]) -> Result<ScanAllIntoOp, PollError> {
+        match self.batch.poll()? {
+            ProbingBatchOp::Idle(batch) =>
+                Ok(ScanAllIntoOp::Idle(ScanAll { batch, })),
+            ProbingBatchOp::Done { readings, mut ready, } => {
+                for &(channel, value) in &readings {
+                    values[u8::from(channel) as usize] = value;
+                }
+                ready.inner.details.spare_readings = readings;
+                Ok(ScanAllIntoOp::Done { ready, })
+            },
+        }
+    }
+}
+
+pub enum ScanAllOp {
+    Idle(ScanAll),
+    Done {
+        // indexed by channel number, channels the chip lacks read 0 V
+        values: [Volt; 8],
+        ready: Ready,
+    },
+}
+
+pub enum ScanAllIntoOp {
+    Idle(ScanAll),
+    Done { ready: Ready, },
+}
+
+// Streaming
+
+pub struct Streaming {
+    samples_rx: mpsc::Receiver<Volt>,
+    inner: Inner,
+}
+
+impl From<Streaming> for Session {
+    fn from(state: Streaming) -> Session {
+        Session::Streaming(state)
+    }
+}
+
+impl Streaming {
+    pub fn samples(&self) -> &mpsc::Receiver<Volt> {
+        &self.samples_rx
+    }
+
+    // samples which were not received yet are discarded; a transfer error which ended the stream
+    // (disconnecting `samples`) is reported here
+    pub fn stop(self) -> Result<Ready, PollError> {
+        self.inner.stop()
+    }
+}
+
+// Watching
+
+pub struct Watching {
+    transitions_rx: mpsc::Receiver<Transition>,
+    inner: Inner,
+}
+
+impl From<Watching> for Session {
+    fn from(state: Watching) -> Session {
+        Session::Watching(state)
+    }
+}
+
+impl Watching {
+    pub fn poll(mut self) -> Result<WatchingOp, PollError> {
+        match self.transitions_rx.try_recv() {
+            Ok(Transition::AboveHigh { value, }) =>
+                Ok(WatchingOp::AboveHigh { value, watching: self, }),
+            Ok(Transition::BelowLow { value, }) =>
+                Ok(WatchingOp::BelowLow { value, watching: self, }),
+            Ok(Transition::BackInRange { value, }) =>
+                Ok(WatchingOp::BackInRange { value, watching: self, }),
+            Err(mpsc::TryRecvError::Empty) =>
+                Ok(WatchingOp::Idle(self)),
+            // the worker has stopped watching, most likely because of an error which is delivered as usual
+            Err(mpsc::TryRecvError::Disconnected) =>
+                loop {
+                    match self.inner.recv_event() {
+                        Ok(Event::Error(error)) =>
+                            return Err(PollError::Recoverable { error, ready: Ready { inner: self.inner, }, }),
+                        Ok(event) =>
+                            skip_event(&event),
+                        Err(mpsc::RecvError) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    }
+                },
+        }
+    }
+
+    pub fn stop(self) -> Result<Ready, PollError> {
+        self.inner.stop()
+    }
+}
+
+pub enum WatchingOp {
+    Idle(Watching),
+    AboveHigh { value: Volt, watching: Watching, },
+    BelowLow { value: Volt, watching: Watching, },
+    BackInRange { value: Volt, watching: Watching, },
+}
+
+// Debouncing
+
+pub struct Debouncing {
+    levels_rx: mpsc::Receiver<LevelChange>,
+    inner: Inner,
+}
+
+impl From<Debouncing> for Session {
+    fn from(state: Debouncing) -> Session {
+        Session::Debouncing(state)
+    }
+}
+
+impl Debouncing {
+    pub fn poll(mut self) -> Result<DebouncingOp, PollError> {
+        match self.levels_rx.try_recv() {
+            Ok(LevelChange { high, value, }) =>
+                Ok(DebouncingOp::Changed { high, value, debouncing: self, }),
+            Err(mpsc::TryRecvError::Empty) =>
+                Ok(DebouncingOp::Idle(self)),
+            // same as for `Watching`: the worker only gives up because of an error
+            Err(mpsc::TryRecvError::Disconnected) =>
+                loop {
+                    match self.inner.recv_event() {
+                        Ok(Event::Error(error)) =>
+                            return Err(PollError::Recoverable { error, ready: Ready { inner: self.inner, }, }),
+                        Ok(event) =>
+                            skip_event(&event),
+                        Err(mpsc::RecvError) =>
+                            return Err(PollError::Fatal(Error::SpiThreadLost)),
+                    }
+                },
+        }
+    }
+
+    pub fn stop(self) -> Result<Ready, PollError> {
+        self.inner.stop()
+    }
+}
+
+pub enum DebouncingOp {
+    Idle(Debouncing),
+    // `value` is the reading which confirmed the level
+    Changed { high: bool, value: Volt, debouncing: Debouncing, },
+}
+
+// SpiTransfer
+
+// the only spi operation the driver needs; it works on plain buffers rather than `Segment`s
+// because rppal segments do not expose their contents, which a fake backend has to inspect
+pub trait SpiTransfer {
+    fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), rppal::spi::Error>;
+}
+
+impl SpiTransfer for Spi {
+    fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), rppal::spi::Error> {
+        self.transfer_segments(&[Segment::new(read_buffer, write_buffer)])
+    }
+}
+
+// BlockingMcp3008
+
+// owns spi directly and converts on the caller's thread, for setups where a worker thread is too much
+pub struct BlockingMcp3008 {
+    spi: Box<dyn SpiTransfer + Send>,
+    buffer: [u8; 3],
+    config: Config,
+}
+
+impl BlockingMcp3008 {
+    pub fn new(params: &Params) -> Result<BlockingMcp3008, Error> {
+        let config = Config::new(params)?;
+        let spi = open_backend(&config)?;
+        Ok(BlockingMcp3008 { spi, buffer: [0, 0, 0], config, })
+    }
+
+    pub fn with_backend(params: &Params, backend: Box<dyn SpiTransfer + Send>) -> Result<BlockingMcp3008, Error> {
+        let config = Config::new(params)?;
+        Ok(BlockingMcp3008 { spi: backend, buffer: [0, 0, 0], config, })
+    }
+
+    pub fn read_channel(&mut self, channel: Channel) -> Result<Volt, Error> {
+        self.config.chip.ensure_supported(channel)?;
+        run_self_test(&*self.spi, &mut self.buffer, &mut self.config)?;
+        let reading = sample_channel(&*self.spi, &mut self.buffer, channel, true, &self.config)?;
+        Ok(reading.value)
+    }
+
+    // the latest failed `Params::self_test` since the previous call, earlier ones are overwritten
+    pub fn take_self_test_failure(&mut self) -> Option<SelfTestFailure> {
+        self.config.self_test_failure.take()
+    }
+}
+
+// inner impl
+
+// fields are dropped in declaration order: both channels are closed before the worker is joined,
+// so the spi thread always observes the disconnect and releases the peripheral
+struct Inner {
+    request_tx: mpsc::SyncSender<Request>,
+    event_rx: mpsc::Receiver<Event>,
+    stale_replies: usize,
+    details: Box<Details>,
+    worker: Worker,
+}
+
+// boxed to keep `Ready` small as it is moved around by every state transition
+struct Details {
+    chip: Chip,
+    v_dd: Volt,
+    // mirrors the worker's `Config::v_ref`
     v_ref: Volt,
+    sample_rate: Arc<SampleRate>,
+    // batch vectors travel to the worker and back, so `poll_into` users stop allocating after the first batch
+    spare_channels: Vec<Channel>,
+    spare_readings: Vec<(Channel, Volt)>,
+    self_test_failure: Option<SelfTestFailure>,
+    // taken off `event_rx` by `is_ready` checks, handed out before anything newer
+    peeked: RefCell<VecDeque<Event>>,
+}
+
+struct Worker {
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Inner {
+    fn ensure_supported(&self, channel: Channel) -> Result<(), Error> {
+        self.details.chip.ensure_supported(channel)
+    }
+
+    // skips replies to requests which were given up on
+    fn try_recv_event(&mut self) -> Result<Event, mpsc::TryRecvError> {
+        loop {
+            let event = match self.details.peeked.get_mut().pop_front() {
+                Some(event) =>
+                    event,
+                None =>
+                    self.event_rx.try_recv()?,
+            };
+            self.note_self_test(&event);
+            match event {
+                Event::ChannelRead { .. } | Event::ChannelsRead { .. } | Event::Error(..) if self.stale_replies > 0 =>
+                    self.stale_replies -= 1,
+                event =>
+                    return Ok(event),
+            }
+        }
+    }
+
+    fn recv_event(&mut self) -> Result<Event, mpsc::RecvError> {
+        loop {
+            let event = match self.details.peeked.get_mut().pop_front() {
+                Some(event) =>
+                    event,
+                None =>
+                    self.event_rx.recv()?,
+            };
+            self.note_self_test(&event);
+            match event {
+                Event::ChannelRead { .. } | Event::ChannelsRead { .. } | Event::Error(..) if self.stale_replies > 0 =>
+                    self.stale_replies -= 1,
+                event =>
+                    return Ok(event),
+            }
+        }
+    }
+
+    // a self-test failure rides along with the next successful reply, which counts even when the reply is stale
+    fn note_self_test(&mut self, event: &Event) {
+        match event {
+            Event::ChannelRead { self_test_failure: Some(failure), .. } |
+            Event::ChannelsRead { self_test_failure: Some(failure), .. } =>
+                self.details.self_test_failure = Some(*failure),
+            _ =>
+                (),
+        }
+    }
+
+    // the worker might still be delivering a stale reply, so those are drained before sending
+    fn send_request(&mut self, request: Request) -> Result<(), Error> {
+        while self.stale_replies > 0 {
+            let event = match self.details.peeked.get_mut().pop_front() {
+                Some(event) =>
+                    Ok(event),
+                None =>
+                    self.event_rx.recv(),
+            };
+            if let Ok(event) = &event {
+                self.note_self_test(event);
+            }
+            match event {
+                Ok(..) =>
+                    self.stale_replies -= 1,
+                Err(mpsc::RecvError) =>
+                    return Err(Error::SpiThreadLost),
+            }
+        }
+        self.request_tx.send(request)
+            .map_err(|mpsc::SendError(..)| Error::SpiThreadLost)
+    }
+
+    // whether a reply which is not stale has arrived or the worker is gone, i.e. `try_recv_event` would not come back
+    // empty; receives into `peeked` so it works on a shared reference
+    fn peek_reply(&self) -> bool {
+        let mut peeked = self.details.peeked.borrow_mut();
+        loop {
+            match self.event_rx.try_recv() {
+                Ok(event) =>
+                    peeked.push_back(event),
+                Err(mpsc::TryRecvError::Empty) =>
+                    break,
+                Err(mpsc::TryRecvError::Disconnected) =>
+                    return true,
+            }
+        }
+        let replies = peeked
+            .iter()
+            .filter(|event| matches!(event, Event::ChannelRead { .. } | Event::ChannelsRead { .. } | Event::Error(..)))
+            .count();
+        replies > self.stale_replies
+    }
+
+    // the rendezvous on `Request::Stop` means that an error which ended the free run has been sent already
+    fn stop(mut self) -> Result<Ready, PollError> {
+        if let Err(error) = self.send_request(Request::Stop) {
+            return Err(PollError::Fatal(error));
+        }
+        loop {
+            match self.try_recv_event() {
+                Ok(Event::Error(error)) =>
+                    return Err(PollError::Recoverable { error, ready: Ready { inner: self, }, }),
+                Ok(event) =>
+                    skip_event(&event),
+                Err(mpsc::TryRecvError::Empty) =>
+                    return Ok(Ready { inner: self, }),
+                Err(mpsc::TryRecvError::Disconnected) =>
+                    return Err(PollError::Fatal(Error::SpiThreadLost)),
+            }
+        }
+    }
+
+    fn shutdown(self) {
+        self.join().ok();
+    }
+
+    fn join(self) -> thread::Result<()> {
+        let Inner { request_tx, event_rx, mut worker, .. } = self;
+        drop(request_tx);
+        drop(event_rx);
+        worker.join()
+    }
+}
+
+impl Worker {
+    fn join(&mut self) -> thread::Result<()> {
+        match self.handle.take() {
+            Some(handle) =>
+                handle.join(),
+            None =>
+                Ok(()),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.handle.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.join().ok();
+    }
+}
+
+enum Request {
+    ProbeChannel { channel: Channel, },
+    ProbeDifferential { pair: DifferentialPair, },
+    // `readings` is an empty vector to fill, possibly with capacity left from an earlier batch
+    ProbeChannels { channels: Vec<Channel>, readings: Vec<(Channel, Volt)>, },
+    SetVref { voltage: Volt, },
+    SetObserver { observer: Box<dyn Fn(Channel, Volt) + Send>, },
+    StreamChannel { channel: Channel, samples_tx: mpsc::SyncSender<Volt>, },
+    Watch { channel: Channel, low: Volt, high: Volt, transitions_tx: mpsc::Sender<Transition>, },
+    Debounce {
+        channel: Channel,
+        threshold: Volt,
+        hysteresis: Volt,
+        stable_for: Duration,
+        levels_tx: mpsc::Sender<LevelChange>,
+    },
+    // ends whichever free-running mode the worker is in
+    Stop,
+    // replied with `Event::SpiInitialized` once spi is open again
+    Reinitialize,
+}
+
+enum Transition {
+    AboveHigh { value: Volt, },
+    BelowLow { value: Volt, },
+    BackInRange { value: Volt, },
+}
+
+struct LevelChange {
+    high: bool,
+    value: Volt,
+}
+
+#[derive(Debug)]
+enum Event {
+    SpiInitialized,
+    // every request gets exactly one event back, so a pending self-test failure is carried by the next reading
+    ChannelRead {
+        channel: Channel,
+        reading: Reading,
+        transfer_duration: Duration,
+        self_test_failure: Option<SelfTestFailure>,
+    },
+    // `channels` is handed back for reuse
+    ChannelsRead {
+        channels: Vec<Channel>,
+        readings: Vec<(Channel, Volt)>,
+        self_test_failure: Option<SelfTestFailure>,
+    },
+    Error(Error),
+}
+
+// the typestate never expects these, but a mismatched event is not worth panicking the caller's thread over
+fn skip_event(event: &Event) {
+    #[cfg(feature = "log")]
+    log::warn!("skipping unexpected event: {:?}", event);
+    #[cfg(not(feature = "log"))]
+    let _ = event;
+}
+
+// everything derived from `Params` needed to open spi and convert readings
+struct Config {
+    bus: Bus,
+    slave_select: SlaveSelect,
+    hz: u32,
+    mode: Mode,
+    v_dd: Volt,
+    v_ref: Volt,
+    chip: Chip,
+    layout: FrameLayout,
+    oversample: u16,
+    filter: FilterMode,
+    sample_interval: Option<Duration>,
+    channel_switch_delay: Option<Duration>,
+    // input the multiplexer was last switched to, as (channel, single_ended)
+    last_input: Cell<Option<(Channel, bool)>>,
+    // time spent inside `SpiTransfer::transfer` since `sample_channel` started
+    transfer_duration: Cell<Duration>,
+    max_retries: u8,
+    retry_delay: Duration,
+    watch_interval: Duration,
+    watch_hysteresis: Volt,
+    calibration: [Calibration; 8],
+    check_null_bit: bool,
+    transform: Option<Transform>,
+    debug_frames: bool,
+    clamp_to_ref: bool,
+    supply_monitor: Option<SupplyMonitor>,
+    self_test: Option<SelfTest>,
+    last_self_test: Option<Instant>,
+    // waits for the next successful reply, an `Event::Error` one leaves it pending
+    self_test_failure: Option<SelfTestFailure>,
+    // installed later through `Ready::with_observer`
+    observer: Option<Box<dyn Fn(Channel, Volt) + Send>>,
+    backend: Backend,
+    sample_rate: Arc<SampleRate>,
+}
+
+impl Config {
+    fn new(params: &Params) -> Result<Config, Error> {
+        let hz = params.clock_hz.unwrap_or(match (&params.chip, &params.voltage_drain) {
+            (Chip::Mcp3002, Vdd::Positive3v3) =>
+                320_000,
+            (Chip::Mcp3002, Vdd::Positive5v) =>
+                1_200_000,
+            (Chip::Mcp3004, Vdd::Positive3v3) | (Chip::Mcp3008, Vdd::Positive3v3) =>
+                1_350_000,
+            (Chip::Mcp3004, Vdd::Positive5v) | (Chip::Mcp3008, Vdd::Positive5v) =>
+                3_600_000,
+            (Chip::Mcp3204, Vdd::Positive3v3) | (Chip::Mcp3208, Vdd::Positive3v3) =>
+                1_000_000,
+            (Chip::Mcp3204, Vdd::Positive5v) | (Chip::Mcp3208, Vdd::Positive5v) =>
+                2_000_000,
+        });
+        let v_dd = match params.voltage_drain {
+            Vdd::Positive3v3 =>
+                Volt(3.3),
+            Vdd::Positive5v =>
+                Volt(5.0),
+        };
+        let v_ref = match params.voltage_ref {
+            Vref::EqualToVdd =>
+                v_dd,
+            Vref::Other { voltage, } =>
+                validate_vref(voltage, v_dd)?,
+        };
+        if let Some(SupplyMonitor { channel, voltage, }) = params.supply_monitor {
+            params.chip.ensure_supported(channel)?;
+            validate_vref(voltage, v_dd)?;
+        }
+        if let Some(SelfTest { channel, .. }) = params.self_test {
+            params.chip.ensure_supported(channel)?;
+        }
+        let layout = match params.framing {
+            Framing::Standard =>
+                params.chip.frame_layout(),
+            Framing::Custom(layout) if layout.is_valid() && layout.result_width() == params.chip.resolution_bits() as u32 =>
+                layout,
+            Framing::Custom(layout) =>
+                return Err(Error::InvalidFraming { layout, }),
+        };
+
+        Ok(Config {
+            bus: params.bus,
+            slave_select: params.slave_select,
+            hz,
+            mode: params.mode,
+            v_dd,
+            v_ref,
+            chip: params.chip,
+            layout,
+            oversample: params.oversample,
+            filter: params.filter,
+            sample_interval: params.sample_interval,
+            channel_switch_delay: params.channel_switch_delay,
+            last_input: Cell::new(None),
+            transfer_duration: Cell::new(Duration::ZERO),
+            max_retries: params.max_retries,
+            retry_delay: params.retry_delay,
+            watch_interval: params.watch_interval,
+            watch_hysteresis: params.watch_hysteresis,
+            calibration: params.calibration,
+            check_null_bit: params.check_null_bit,
+            transform: params.transform.clone(),
+            debug_frames: params.debug_frames,
+            clamp_to_ref: params.clamp_to_ref,
+            supply_monitor: params.supply_monitor,
+            self_test: params.self_test,
+            last_self_test: None,
+            self_test_failure: None,
+            observer: None,
+            backend: params.backend.clone(),
+            sample_rate: Arc::new(SampleRate::new()),
+        })
+    }
+}
+
+const SAMPLE_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// transfers are counted over consecutive windows, the rate of the last complete one is reported
+struct SampleRate {
+    state: Mutex<SampleRateState>,
+}
+
+struct SampleRateState {
+    window_start: Instant,
+    transfers: u64,
+    hz: f64,
+}
+
+impl SampleRate {
+    fn new() -> SampleRate {
+        SampleRate {
+            state: Mutex::new(SampleRateState { window_start: Instant::now(), transfers: 0, hz: 0.0, }),
+        }
+    }
+
+    fn record_transfer(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.transfers += 1;
+        let now = Instant::now();
+        let elapsed = now - state.window_start;
+        if elapsed >= SAMPLE_RATE_WINDOW {
+            state.hz = state.transfers as f64 / elapsed.as_secs_f64();
+            state.window_start = now;
+            state.transfers = 0;
+        }
+    }
+
+    fn hz(&self) -> f64 {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = state.window_start.elapsed();
+        // the window only rolls over on a transfer, so an idle worker would keep reporting a stale rate
+        if elapsed >= SAMPLE_RATE_WINDOW {
+            state.transfers as f64 / elapsed.as_secs_f64()
+        } else {
+            state.hz
+        }
+    }
+}
+
+fn spi_worker(
+    request_rx: mpsc::Receiver<Request>,
+    event_tx: mpsc::SyncSender<Event>,
+    backend: Option<Box<dyn SpiTransfer + Send>>,
+    mut config: Config,
+)
+{
+    match spi_worker_loop(request_rx, &event_tx, backend, &mut config) {
+        Ok(()) => {
+            #[cfg(feature = "log")]
+            log::debug!("spi worker for {:?}/{:?} exits: session closed", config.bus, config.slave_select);
+        },
+        Err(error) => {
+            #[cfg(feature = "log")]
+            log::error!(
+                "spi worker for {:?}/{:?} at {} Hz terminated: {}",
+                config.bus,
+                config.slave_select,
+                config.hz,
+                error,
+            );
+            event_tx.send(Event::Error(error)).ok();
+        },
+    }
+}
+
+fn spi_worker_loop(
+    request_rx: mpsc::Receiver<Request>,
+    event_tx: &mpsc::SyncSender<Event>,
+    backend: Option<Box<dyn SpiTransfer + Send>>,
+    config: &mut Config,
 )
     -> Result<(), Error>
 {
-    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, hz, Mode::Mode0)
-        .map_err(Error::SpiInitialize)?;
+    let mut backend = backend;
     let mut buffer: [u8; 3] = [0, 0, 0];
+    loop {
+        let injected = backend.is_some();
+        let spi = match backend.take() {
+            Some(backend) =>
+                backend,
+            None =>
+                open_backend(config)?,
+        };
+        // the multiplexer state is unknown after reopening
+        config.last_input.set(None);
+
+        #[cfg(feature = "log")]
+        log::debug!("spi initialized on {:?}/{:?} at {} Hz", config.bus, config.slave_select, config.hz);
+
+        event_tx.send(Event::SpiInitialized)
+            .or_else(|mpsc::SendError(..)| Ok(()))?;
+
+        match serve_requests(&request_rx, event_tx, &*spi, &mut buffer, config)? {
+            Served::Closed =>
+                return Ok(()),
+            Served::Reinitialize if injected =>
+                backend = Some(spi),
+            // `spi` is dropped here, closing the device before it is opened again
+            Served::Reinitialize =>
+                (),
+        }
+    }
+}
 
-    event_tx.send(Event::SpiInitialized)
-        .or_else(|mpsc::SendError(..)| Ok(()))?;
+enum Served {
+    Closed,
+    Reinitialize,
+}
 
+fn serve_requests(
+    request_rx: &mpsc::Receiver<Request>,
+    event_tx: &mpsc::SyncSender<Event>,
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    config: &mut Config,
+)
+    -> Result<Served, Error>
+{
     loop {
         match request_rx.recv() {
+            // a failed transfer is the reply to its request, the worker carries on afterwards
             Ok(Request::ProbeChannel { channel, }) => {
-                let channel_value = match channel {
-                    Channel::Ch0 => 0,
-                    Channel::Ch1 => 1,
-                    Channel::Ch2 => 2,
-                    Channel::Ch3 => 3,
-                    Channel::Ch4 => 4,
-                    Channel::Ch5 => 5,
-                    Channel::Ch6 => 6,
-                    Channel::Ch7 => 7,
+                let result = run_self_test(spi, buffer, config)
+                    .and_then(|()| sample_channel(spi, buffer, channel, true, config));
+                let event = match result {
+                    Ok(reading) =>
+                        Event::ChannelRead {
+                            channel,
+                            reading,
+                            transfer_duration: config.transfer_duration.get(),
+                            self_test_failure: config.self_test_failure.take(),
+                        },
+                    Err(error) =>
+                        Event::Error(error),
                 };
-                spi.transfer_segments(
-                    &[Segment::new(&mut buffer, &[0b00000001, 0b10000000 | (channel_value << 4), 0b00000000])],
-                ).map_err(Error::SpiTransferSegments)?;
-                let data = ((buffer[1] & 0b00000011) as u16) << 8 | (buffer[2] as u16);
-                let value = Volt(data as f64 * v_ref.0 / 1024.0);
-
-                event_tx.send(Event::ChannelRead { channel, value, })
+                event_tx.send(event)
+                    .or_else(|mpsc::SendError(..)| Ok(()))?;
+            },
+            Ok(Request::ProbeDifferential { pair, }) => {
+                let channel = pair.positive();
+                let result = run_self_test(spi, buffer, config)
+                    .and_then(|()| sample_channel(spi, buffer, channel, false, config));
+                let event = match result {
+                    Ok(reading) =>
+                        Event::ChannelRead {
+                            channel,
+                            reading,
+                            transfer_duration: config.transfer_duration.get(),
+                            self_test_failure: config.self_test_failure.take(),
+                        },
+                    Err(error) =>
+                        Event::Error(error),
+                };
+                event_tx.send(event)
+                    .or_else(|mpsc::SendError(..)| Ok(()))?;
+            },
+            Ok(Request::ProbeChannels { channels, mut readings, }) => {
+                // transfer everything first so the grouped samples are as close in time as possible
+                readings.clear();
+                readings.reserve(channels.len());
+                let result = run_self_test(spi, buffer, config)
+                    .and_then(|()| channels
+                        .iter()
+                        .try_for_each(|&channel| {
+                            let Reading { value, .. } = sample_channel(spi, buffer, channel, true, config)?;
+                            readings.push((channel, value));
+                            Ok(())
+                        }));
+                let event = match result {
+                    Ok(()) =>
+                        Event::ChannelsRead { channels, readings, self_test_failure: config.self_test_failure.take(), },
+                    Err(error) =>
+                        Event::Error(error),
+                };
+                event_tx.send(event)
                     .or_else(|mpsc::SendError(..)| Ok(()))?;
             },
+            Ok(Request::SetVref { voltage, }) =>
+                config.v_ref = voltage,
+            Ok(Request::SetObserver { observer, }) =>
+                config.observer = Some(observer),
+            Ok(Request::StreamChannel { channel, samples_tx, }) =>
+                loop {
+                    match poll_free_run(request_rx) {
+                        FreeRun::Continue | FreeRun::Unexpected =>
+                            (),
+                        FreeRun::Stop =>
+                            break,
+                        FreeRun::Disconnected =>
+                            return Ok(Served::Closed),
+                    }
+                    let result = run_self_test(spi, buffer, config)
+                        .and_then(|()| sample_channel(spi, buffer, channel, true, config));
+                    let Reading { value, .. } = match result {
+                        Ok(reading) =>
+                            reading,
+                        Err(error) => {
+                            // dropping `samples_tx` tells the consumer, `Streaming::stop` picks the error up
+                            event_tx.send(Event::Error(error))
+                                .or_else(|mpsc::SendError(..)| Ok(()))?;
+                            break;
+                        },
+                    };
+                    // a slow consumer loses samples rather than stalling the worker
+                    samples_tx.try_send(value).ok();
+                },
+            Ok(Request::Watch { channel, low, high, transitions_tx, }) => {
+                let mut zone = Zone::InRange;
+                loop {
+                    match poll_free_run(request_rx) {
+                        FreeRun::Continue | FreeRun::Unexpected =>
+                            (),
+                        FreeRun::Stop =>
+                            break,
+                        FreeRun::Disconnected =>
+                            return Ok(Served::Closed),
+                    }
+                    let result = run_self_test(spi, buffer, config)
+                        .and_then(|()| sample_channel(spi, buffer, channel, true, config));
+                    let Reading { value, .. } = match result {
+                        Ok(reading) =>
+                            reading,
+                        Err(error) => {
+                            event_tx.send(Event::Error(error))
+                                .or_else(|mpsc::SendError(..)| Ok(()))?;
+                            break;
+                        },
+                    };
+                    let next_zone = match zone {
+                        _ if value.0 > high.0 =>
+                            Zone::Above,
+                        _ if value.0 < low.0 =>
+                            Zone::Below,
+                        Zone::Above if value.0 > high.0 - config.watch_hysteresis.0 =>
+                            Zone::Above,
+                        Zone::Below if value.0 < low.0 + config.watch_hysteresis.0 =>
+                            Zone::Below,
+                        _ =>
+                            Zone::InRange,
+                    };
+                    if next_zone != zone {
+                        zone = next_zone;
+                        let transition = match zone {
+                            Zone::InRange =>
+                                Transition::BackInRange { value, },
+                            Zone::Above =>
+                                Transition::AboveHigh { value, },
+                            Zone::Below =>
+                                Transition::BelowLow { value, },
+                        };
+                        transitions_tx.send(transition).ok();
+                    }
+                    thread::sleep(config.watch_interval);
+                }
+            },
+            Ok(Request::Debounce { channel, threshold, hysteresis, stable_for, levels_tx, }) => {
+                // `schmitt` is the level after magnitude hysteresis, `level` the last one which held long enough
+                let mut schmitt = None;
+                let mut since = Instant::now();
+                let mut level = None;
+                loop {
+                    match poll_free_run(request_rx) {
+                        FreeRun::Continue | FreeRun::Unexpected =>
+                            (),
+                        FreeRun::Stop =>
+                            break,
+                        FreeRun::Disconnected =>
+                            return Ok(Served::Closed),
+                    }
+                    let result = run_self_test(spi, buffer, config)
+                        .and_then(|()| sample_channel(spi, buffer, channel, true, config));
+                    let Reading { value, timestamp, .. } = match result {
+                        Ok(reading) =>
+                            reading,
+                        Err(error) => {
+                            event_tx.send(Event::Error(error))
+                                .or_else(|mpsc::SendError(..)| Ok(()))?;
+                            break;
+                        },
+                    };
+                    let high = match schmitt {
+                        Some(true) =>
+                            value.0 >= threshold.0 - hysteresis.0 / 2.0,
+                        Some(false) =>
+                            value.0 > threshold.0 + hysteresis.0 / 2.0,
+                        None =>
+                            value.0 > threshold.0,
+                    };
+                    if schmitt != Some(high) {
+                        schmitt = Some(high);
+                        since = timestamp;
+                    }
+                    if level != Some(high) && timestamp.duration_since(since) >= stable_for {
+                        level = Some(high);
+                        levels_tx.send(LevelChange { high, value, }).ok();
+                    }
+                    thread::sleep(config.watch_interval);
+                }
+            },
+            Ok(Request::Stop) =>
+                (),
+            Ok(Request::Reinitialize) =>
+                return Ok(Served::Reinitialize),
             Err(mpsc::RecvError) =>
-                return Ok(()),
+                return Ok(Served::Closed),
+        }
+    }
+}
+
+fn open_backend(config: &Config) -> Result<Box<dyn SpiTransfer + Send>, Error> {
+    match config.backend {
+        Backend::Spi =>
+            Ok(Box::new(open_spi(config)?)),
+        Backend::Simulated { ref waveforms, } =>
+            Ok(Box::new(mock::SimulatedSpi::new(**waveforms, config.v_ref, config.layout))),
+    }
+}
+
+fn open_spi(config: &Config) -> Result<Spi, Error> {
+    #[cfg(feature = "log")]
+    {
+        if let Mode::Mode1 | Mode::Mode2 = config.mode {
+            log::warn!("{:?} is not supported by the chip, expect garbage readings", config.mode);
+        }
+    }
+    let spi = Spi::new(config.bus, config.slave_select, config.hz, config.mode)
+        .map_err(Error::SpiInitialize)?;
+    // `FrameLayout` positions assume MSB first, so it is set explicitly instead of relying on the default
+    spi.set_bit_order(BitOrder::MsbFirst)
+        .map_err(Error::SpiInitialize)?;
+    Ok(spi)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    InRange,
+    Above,
+    Below,
+}
+
+enum FreeRun {
+    Continue,
+    Stop,
+    // the typestate only sends `Request::Stop` during a free run, anything else is dropped and sampling goes on
+    Unexpected,
+    Disconnected,
+}
+
+// checked between samples while the worker is free-running
+fn poll_free_run(request_rx: &mpsc::Receiver<Request>) -> FreeRun {
+    match request_rx.try_recv() {
+        Ok(Request::Stop) =>
+            FreeRun::Stop,
+        Ok(..) => {
+            #[cfg(feature = "log")]
+            log::warn!("skipping unexpected request during a free run");
+            FreeRun::Unexpected
+        },
+        Err(mpsc::TryRecvError::Empty) =>
+            FreeRun::Continue,
+        Err(mpsc::TryRecvError::Disconnected) =>
+            FreeRun::Disconnected,
+    }
+}
+
+fn run_self_test(spi: &dyn SpiTransfer, buffer: &mut [u8; 3], config: &mut Config) -> Result<(), Error> {
+    let self_test = match config.self_test {
+        Some(self_test) if !matches!(config.last_self_test, Some(last) if last.elapsed() < self_test.interval) =>
+            self_test,
+        _ =>
+            return Ok(()),
+    };
+    config.last_self_test = Some(Instant::now());
+    let Reading { value: measured, .. } = convert_channel(spi, buffer, self_test.channel, true, config)?;
+    // written this way to fail on NaN as well
+    if (measured.0 - self_test.expected.0).abs() <= self_test.tolerance.0 {
+        return Ok(());
+    }
+
+    #[cfg(feature = "log")]
+    log::warn!(
+        "self-test on {:?} failed: measured {:?}, expected {:?} within {:?}",
+        self_test.channel,
+        measured,
+        self_test.expected,
+        self_test.tolerance,
+    );
+
+    config.self_test_failure = Some(SelfTestFailure { measured, expected: self_test.expected, });
+    Ok(())
+}
+
+fn sample_channel(
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    channel: Channel,
+    single_ended: bool,
+    config: &Config,
+)
+    -> Result<Reading, Error>
+{
+    let reading = convert_channel(spi, buffer, channel, single_ended, config)?;
+    if let Some(observer) = &config.observer {
+        observer(channel, reading.value);
+    }
+    Ok(reading)
+}
+
+fn convert_channel(
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    channel: Channel,
+    single_ended: bool,
+    config: &Config,
+)
+    -> Result<Reading, Error>
+{
+    let resolution_bits = config.chip.resolution_bits();
+    let full_scale = (1u32 << resolution_bits) as f64;
+    config.transfer_duration.set(Duration::ZERO);
+    // the monitor goes first, so `buffer` ends up with the frame of the requested channel
+    let v_ref = match config.supply_monitor {
+        Some(SupplyMonitor { channel: monitor, voltage, }) if monitor != channel || !single_ended => {
+            let monitor_count = filter_channel(spi, buffer, monitor, true, config)?;
+            if monitor_count < 1.0 {
+                return Err(Error::ImplausibleReading { channel: monitor, });
+            }
+            Volt(voltage.0 * full_scale / monitor_count)
+        },
+        _ =>
+            config.v_ref,
+    };
+    let filtered = filter_channel(spi, buffer, channel, single_ended, config)?;
+    let timestamp = Instant::now();
+    let uncalibrated = Volt(filtered * v_ref.0 / full_scale);
+    let calibration = &config.calibration[u8::from(channel) as usize];
+    let mut value = Volt(uncalibrated.0 * calibration.gain + calibration.offset);
+    if config.clamp_to_ref {
+        value = Volt(value.0.clamp(0.0, v_ref.0));
+    }
+    Ok(Reading {
+        value,
+        uncalibrated,
+        raw: filtered.round() as u16,
+        resolution_bits,
+        engineering: config.transform.as_ref().map(|Transform(transform)| transform(value)),
+        // still holds the response of the last transfer
+        frame: if config.debug_frames { Some(*buffer) } else { None },
+        timestamp,
+    })
+}
+
+// count combined from raw samples according to `config.filter`
+fn filter_channel(
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    channel: Channel,
+    single_ended: bool,
+    config: &Config,
+)
+    -> Result<f64, Error>
+{
+    match config.filter {
+        FilterMode::None =>
+            oversample_channel(spi, buffer, channel, single_ended, config),
+        FilterMode::Mean(length) => {
+            let length = length.max(1);
+            let mut sum = 0.0;
+            for index in 0 .. length {
+                if index > 0 {
+                    sample_gap(config);
+                }
+                sum += oversample_channel(spi, buffer, channel, single_ended, config)?;
+            }
+            Ok(sum / length as f64)
+        },
+        FilterMode::Median(length) => {
+            let length = length.max(1) as usize;
+            let mut samples = Vec::with_capacity(length);
+            for index in 0 .. length {
+                if index > 0 {
+                    sample_gap(config);
+                }
+                samples.push(oversample_channel(spi, buffer, channel, single_ended, config)?);
+            }
+            samples.sort_by(f64::total_cmp);
+            if length % 2 == 1 {
+                Ok(samples[length / 2])
+            } else {
+                Ok((samples[length / 2 - 1] + samples[length / 2]) / 2.0)
+            }
+        },
+    }
+}
+
+// mean count of `config.oversample` consecutive transfers
+fn oversample_channel(
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    channel: Channel,
+    single_ended: bool,
+    config: &Config,
+)
+    -> Result<f64, Error>
+{
+    let count = config.oversample.max(1);
+    let mut sum: u32 = 0;
+    for index in 0 .. count {
+        if index > 0 {
+            sample_gap(config);
+        }
+        sum += transfer_channel(spi, buffer, channel, single_ended, config)? as u32;
+    }
+    Ok(sum as f64 / count as f64)
+}
+
+fn sample_gap(config: &Config) {
+    if let Some(sample_interval) = config.sample_interval {
+        thread::sleep(sample_interval);
+    }
+}
+
+fn transfer_channel(
+    spi: &dyn SpiTransfer,
+    buffer: &mut [u8; 3],
+    channel: Channel,
+    single_ended: bool,
+    config: &Config,
+)
+    -> Result<u16, Error>
+{
+    let command = config.layout.command(u8::from(channel), single_ended);
+    let frame_len = config.layout.frame_len();
+    if let Some(channel_switch_delay) = config.channel_switch_delay {
+        let input = Some((channel, single_ended));
+        if config.last_input.get() != input {
+            // the input is only sampled during a conversion, so a discarded one connects the new input
+            // and the delay lets it settle before the conversion which counts
+            transfer_frame(spi, &mut buffer[.. frame_len], &command[.. frame_len], channel, config)?;
+            config.last_input.set(input);
+            thread::sleep(channel_switch_delay);
+        }
+    }
+    transfer_frame(spi, &mut buffer[.. frame_len], &command[.. frame_len], channel, config)?;
+    if config.check_null_bit && config.layout.null_bit_set(buffer) {
+        return Err(Error::ImplausibleReading { channel, });
+    }
+    let raw = config.layout.decode(buffer);
+
+    #[cfg(feature = "log")]
+    log::trace!("transfer {:?} on {:?}/{:?}: raw = {}", channel, config.bus, config.slave_select, raw);
+
+    Ok(raw)
+}
+
+// retried up to `config.max_retries` times
+fn transfer_frame(
+    spi: &dyn SpiTransfer,
+    read_buffer: &mut [u8],
+    write_buffer: &[u8],
+    channel: Channel,
+    config: &Config,
+)
+    -> Result<(), Error>
+{
+    #[cfg(not(feature = "log"))]
+    let _ = channel;
+    let mut retries_left = config.max_retries;
+    loop {
+        match timed_transfer(spi, read_buffer, write_buffer, config) {
+            Ok(()) => {
+                config.sample_rate.record_transfer();
+                return Ok(());
+            },
+            Err(_error) if retries_left > 0 => {
+                #[cfg(feature = "log")]
+                log::warn!(
+                    "spi transfer for {:?} on {:?}/{:?} failed, {} retries left: {}",
+                    channel,
+                    config.bus,
+                    config.slave_select,
+                    retries_left,
+                    _error,
+                );
+                retries_left -= 1;
+                thread::sleep(config.retry_delay);
+            },
+            Err(error) =>
+                return Err(Error::SpiTransferSegments(error)),
+        }
+    }
+}
+
+fn timed_transfer(
+    spi: &dyn SpiTransfer,
+    read_buffer: &mut [u8],
+    write_buffer: &[u8],
+    config: &Config,
+)
+    -> Result<(), rppal::spi::Error>
+{
+    let started = Instant::now();
+    let result = spi.transfer(read_buffer, write_buffer);
+    config.transfer_duration.set(config.transfer_duration.get() + started.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Condvar;
+
+    use super::{
+        *,
+        mock::MockSpi,
+    };
+
+    fn read(ready: Ready, channel: Channel) -> (Reading, Ready) {
+        finish(ready.probe_channel(channel).unwrap())
+    }
+
+    fn finish(mut probing: Probing) -> (Reading, Ready) {
+        loop {
+            match probing.poll() {
+                Ok(ProbingOp::Idle(next)) =>
+                    probing = next,
+                Ok(ProbingOp::Done { reading, ready, .. }) =>
+                    return (reading, ready),
+                Err(PollError::Fatal(error)) | Err(PollError::Recoverable { error, .. }) =>
+                    panic!("probe failed: {}", error),
+            }
+        }
+    }
+
+    fn assert_volts(actual: Volt, expected: f64) {
+        assert!((actual.0 - expected).abs() < 1e-9, "{} V instead of {} V", actual.0, expected);
+    }
+
+    #[test]
+    fn mock_scales_10_bit_counts() {
+        let params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        let mock = MockSpi::new([0, 1, 512, 1023, 0, 0, 0, 0]);
+        let ready = Session::open_with_backend(&params, Box::new(mock)).unwrap();
+
+        let (reading, ready) = read(ready, Channel::Ch0);
+        assert_eq!(reading.raw, 0);
+        assert_volts(reading.value, 0.0);
+        assert_eq!(reading.fraction(), 0.0);
+
+        let (reading, ready) = read(ready, Channel::Ch1);
+        assert_eq!(reading.raw, 1);
+        assert_volts(reading.value, 3.3 / 1024.0);
+
+        let (reading, ready) = read(ready, Channel::Ch2);
+        assert_eq!(reading.raw, 512);
+        assert_eq!(reading.resolution_bits, 10);
+        assert_volts(reading.value, 1.65);
+        assert_eq!(reading.fraction(), 512.0 / 1023.0);
+
+        let (reading, ready) = read(ready, Channel::Ch3);
+        assert_eq!(reading.raw, 1023);
+        assert_volts(reading.value, 1023.0 * 3.3 / 1024.0);
+        assert_eq!(reading.fraction(), 1.0);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn mock_scales_12_bit_counts() {
+        let mut params = Params::new(Vdd::Positive5v, Vref::Other { voltage: Volt(4.096), });
+        params.chip = Chip::Mcp3208;
+        let mock = MockSpi::new([0, 0, 0, 0, 0, 2048, 1, 4095]);
+        let ready = Session::open_with_backend(&params, Box::new(mock)).unwrap();
+
+        let (reading, ready) = read(ready, Channel::Ch5);
+        assert_eq!(reading.raw, 2048);
+        assert_eq!(reading.resolution_bits, 12);
+        assert_volts(reading.value, 2.048);
+        assert_eq!(reading.fraction(), 2048.0 / 4095.0);
+
+        let (reading, ready) = read(ready, Channel::Ch6);
+        assert_eq!(reading.raw, 1);
+        assert_volts(reading.value, 0.001);
+
+        let (reading, ready) = read(ready, Channel::Ch7);
+        assert_eq!(reading.raw, 4095);
+        assert_volts(reading.value, 4.095);
+        assert_eq!(reading.fraction(), 1.0);
+
+        Session::from(ready).shutdown();
+    }
+
+    fn reading_of(value: f64) -> Reading {
+        Reading {
+            value: Volt(value),
+            uncalibrated: Volt(value),
+            raw: 0,
+            resolution_bits: 10,
+            engineering: None,
+            frame: None,
+            timestamp: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn millivolts_round_halves_away_from_zero() {
+        assert_eq!(reading_of(0.0005).as_millivolts_i32(), 1);
+        assert_eq!(reading_of(-0.0005).as_millivolts_i32(), -1);
+        assert_eq!(reading_of(0.0025).as_millivolts_i32(), 3);
+        assert_eq!(reading_of(-0.0025).as_millivolts_i32(), -3);
+        assert_eq!(reading_of(1.2345).as_millivolts_i32(), 1235);
+        assert_eq!(reading_of(0.0004).as_millivolts_i32(), 0);
+        assert_eq!(reading_of(-0.0004).as_millivolts_i32(), 0);
+        assert_eq!(reading_of(3.3).as_millivolts_i32(), 3300);
+    }
+
+    #[test]
+    fn millivolts_saturate_and_map_nan_to_zero() {
+        assert_eq!(reading_of(1e12).as_millivolts_i32(), i32::MAX);
+        assert_eq!(reading_of(-1e12).as_millivolts_i32(), i32::MIN);
+        assert_eq!(reading_of(f64::INFINITY).as_millivolts_i32(), i32::MAX);
+        assert_eq!(reading_of(f64::NEG_INFINITY).as_millivolts_i32(), i32::MIN);
+        assert_eq!(reading_of(f64::NAN).as_millivolts_i32(), 0);
+    }
+
+    #[test]
+    fn read_all_skips_channels_the_chip_lacks() {
+        let mut params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        params.chip = Chip::Mcp3004;
+        let mock = MockSpi::new([1023, 512, 256, 0, 1023, 1023, 1023, 1023]);
+        let ready = Session::open_with_backend(&params, Box::new(mock)).unwrap();
+
+        let mut scan = ready.read_all();
+        let (values, ready) = loop {
+            match scan.poll() {
+                Ok(ScanAllOp::Idle(next)) =>
+                    scan = next,
+                Ok(ScanAllOp::Done { values, ready, }) =>
+                    break (values, ready),
+                Err(PollError::Fatal(error)) | Err(PollError::Recoverable { error, .. }) =>
+                    panic!("scan failed: {}", error),
+            }
+        };
+        assert_volts(values[0], 1023.0 * 3.3 / 1024.0);
+        assert_volts(values[1], 1.65);
+        assert_volts(values[2], 0.825);
+        for &value in &values[3 ..] {
+            assert_volts(value, 0.0);
+        }
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn open_ready_waits_forever_on_duration_max() {
+        let params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        let mock = MockSpi::new([0, 0, 0, 0, 0, 0, 0, 0]);
+        let ready = Session::open_ready_with_backend(&params, Box::new(mock), Duration::MAX).unwrap();
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn open_ready_with_backend_hands_back_ready() {
+        let params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        let mock = MockSpi::new([0, 0, 0, 0, 0, 0, 0, 1023]);
+        let ready = Session::open_ready_with_backend(&params, Box::new(mock), Duration::from_secs(5)).unwrap();
+
+        let (reading, ready) = read(ready, Channel::Ch7);
+        assert_eq!(reading.raw, 1023);
+
+        Session::from(ready).shutdown();
+    }
+
+    fn simulated(mut params: Params) -> Ready {
+        let mut waveforms = [Waveform::Constant { voltage: Volt(0.0), }; 8];
+        waveforms[0] = Waveform::Constant { voltage: Volt(2.0), };
+        waveforms[1] = Waveform::Constant { voltage: Volt(0.5), };
+        params.backend = Backend::Simulated { waveforms: Box::new(waveforms), };
+        Session::open(&params).unwrap()
+    }
+
+    #[test]
+    fn simulated_backend_decodes_differential_reads() {
+        let ready = simulated(Params::new(Vdd::Positive3v3, Vref::EqualToVdd));
+
+        let (reading, ready) = read(ready, Channel::Ch0);
+        assert_eq!(reading.raw, 620);
+
+        let (reading, ready) = finish(ready.probe_differential(DifferentialPair::Ch0PlusCh1Minus).unwrap());
+        assert_eq!(reading.raw, 465);
+
+        // IN+ below IN- saturates at zero like the chip does
+        let (reading, ready) = finish(ready.probe_differential(DifferentialPair::Ch0MinusCh1Plus).unwrap());
+        assert_eq!(reading.raw, 0);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn simulated_backend_frames_two_byte_mcp3002() {
+        let mut params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        params.chip = Chip::Mcp3002;
+        let ready = simulated(params);
+
+        let (reading, ready) = read(ready, Channel::Ch1);
+        assert_eq!(reading.raw, 155);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn simulated_backend_follows_custom_framing() {
+        // the standard MCP3008 layout one clock earlier, leaving a trailing bit after the result
+        let layout = FrameLayout {
+            command: [0b00000010, 0b00000000, 0b00000000],
+            single_ended_bit: 16,
+            channel_shift: 13,
+            result_shift: 1,
+            result_mask: 0b0000_0011_1111_1111,
+        };
+        let mut params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        params.framing = Framing::Custom(layout);
+        params.check_null_bit = true;
+        let ready = simulated(params);
+
+        let (reading, ready) = read(ready, Channel::Ch1);
+        assert_eq!(reading.raw, 155);
+
+        let (reading, ready) = finish(ready.probe_differential(DifferentialPair::Ch0PlusCh1Minus).unwrap());
+        assert_eq!(reading.raw, 465);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn custom_framing_out_of_range_is_rejected() {
+        let layout = FrameLayout { result_shift: 250, ..Chip::Mcp3008.frame_layout() };
+        let params = Params::builder()
+            .framing(Framing::Custom(layout))
+            .build();
+        assert!(matches!(params, Err(Error::InvalidFraming { .. })));
+    }
+
+    #[test]
+    fn custom_framing_wider_than_the_chip_is_rejected() {
+        let layout = FrameLayout { result_mask: 0b0000_1111_1111_1111, ..Chip::Mcp3008.frame_layout() };
+        let mut params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        params.framing = Framing::Custom(layout);
+        params.backend = Backend::Simulated { waveforms: Box::new([Waveform::Constant { voltage: Volt(3.0), }; 8]), };
+        assert!(matches!(Session::open(&params), Err(Error::InvalidFraming { .. })));
+
+        // the same mask is fine on a 12-bit part, and the simulated counts use the full width
+        params.chip = Chip::Mcp3208;
+        params.framing = Framing::Custom(FrameLayout { result_mask: 0b0000_1111_1111_1111, ..Chip::Mcp3208.frame_layout() });
+        let (reading, ready) = read(Session::open(&params).unwrap(), Channel::Ch3);
+        assert_eq!(reading.raw, 3723);
+        assert_volts(reading.value, 3723.0 * 3.3 / 4096.0);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn blocking_reads_run_the_self_test() {
+        let params = Params::builder()
+            .self_test(Channel::Ch7, Volt(1.65), Volt(0.01), Duration::ZERO)
+            .build()
+            .unwrap();
+        let mock = MockSpi::new([0, 0, 0, 0, 0, 0, 0, 512]);
+        let mut mcp = BlockingMcp3008::with_backend(&params, Box::new(mock.clone())).unwrap();
+
+        mcp.read_channel(Channel::Ch0).unwrap();
+        assert!(mcp.take_self_test_failure().is_none());
+
+        mock.set(Channel::Ch7, 256);
+        mcp.read_channel(Channel::Ch0).unwrap();
+        let failure = mcp.take_self_test_failure().unwrap();
+        assert_volts(failure.measured, 0.825);
+        assert_volts(failure.expected, 1.65);
+        assert!(mcp.take_self_test_failure().is_none());
+    }
+
+    // holds every transfer until the test hands out a permit, so a probe can be timed out while the worker is
+    // still busy with it and its reply made to arrive late on purpose
+    #[derive(Clone)]
+    struct GatedSpi {
+        mock: MockSpi,
+        // (permits, transfers entered so far)
+        gate: Arc<(Mutex<(usize, usize)>, Condvar)>,
+    }
+
+    impl GatedSpi {
+        fn new(counts: [u16; 8]) -> GatedSpi {
+            GatedSpi { mock: MockSpi::new(counts), gate: Arc::new((Mutex::new((0, 0)), Condvar::new())), }
+        }
+
+        fn release(&self) {
+            let (state, condvar) = &*self.gate;
+            state.lock().unwrap().0 += 1;
+            condvar.notify_all();
+        }
+
+        fn wait_entered(&self, transfers: usize) {
+            let (state, condvar) = &*self.gate;
+            let _state = condvar.wait_while(state.lock().unwrap(), |&mut (_, entered)| entered < transfers).unwrap();
+        }
+    }
+
+    impl SpiTransfer for GatedSpi {
+        fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), rppal::spi::Error> {
+            let (state, condvar) = &*self.gate;
+            let mut state = state.lock().unwrap();
+            state.1 += 1;
+            condvar.notify_all();
+            let mut state = condvar.wait_while(state, |&mut (permits, _)| permits == 0).unwrap();
+            state.0 -= 1;
+            drop(state);
+            self.mock.transfer(read_buffer, write_buffer)
+        }
+    }
+
+    fn gated() -> (GatedSpi, Ready) {
+        let params = Params::new(Vdd::Positive3v3, Vref::EqualToVdd);
+        let spi = GatedSpi::new([100, 200, 300, 400, 500, 600, 700, 800]);
+        let ready = Session::open_with_backend(&params, Box::new(spi.clone())).unwrap();
+        (spi, ready)
+    }
+
+    // leaves the worker stuck in transfer number `transfers` of a Ch0 probe which has been given up on
+    fn time_out_probe(spi: &GatedSpi, ready: Ready, transfers: usize) -> Ready {
+        let mut probing = ready.probe_channel(Channel::Ch0).unwrap();
+        loop {
+            match probing.poll() {
+                Ok(ProbingOp::Idle(next)) if matches!(next.state, ProbingState::WaitingReply) => {
+                    probing = next;
+                    break;
+                },
+                Ok(ProbingOp::Idle(next)) =>
+                    probing = next,
+                _ =>
+                    panic!("probe finished while its transfer is held"),
+            }
+        }
+        spi.wait_entered(transfers);
+        match probing.poll_deadline(Instant::now()) {
+            Err(PollError::Recoverable { error: Error::ProbeTimeout { channel: Channel::Ch0, }, ready, }) => {
+                assert_eq!(ready.inner.stale_replies, 1);
+                ready
+            },
+            _ =>
+                panic!("held probe did not time out"),
+        }
+    }
+
+    fn release_later(spi: &GatedSpi, permits: usize) -> thread::JoinHandle<()> {
+        let spi = spi.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            for _ in 0 .. permits {
+                spi.release();
+            }
+        })
+    }
+
+    #[test]
+    fn late_reply_is_not_taken_for_the_next_request() {
+        let (spi, ready) = gated();
+
+        // the late Ch0 reply is already queued when the next probe starts
+        let ready = time_out_probe(&spi, ready, 1);
+        spi.release();
+        spi.release();
+        let (reading, ready) = read(ready, Channel::Ch1);
+        assert_eq!(reading.raw, 200);
+        assert_eq!(ready.inner.stale_replies, 0);
+
+        // `read_blocking` has to wait for the late reply before its own request gets through
+        let mut ready = time_out_probe(&spi, ready, 3);
+        let releaser = release_later(&spi, 2);
+        assert_volts(ready.read_blocking(Channel::Ch2).unwrap(), 300.0 * 3.3 / 1024.0);
+        releaser.join().unwrap();
+        assert_eq!(ready.inner.stale_replies, 0);
+
+        // `set_vref` has no reply of its own, the next reading must still be its own and use the new reference
+        let ready = time_out_probe(&spi, ready, 5);
+        let releaser = release_later(&spi, 1);
+        let ready = ready.set_vref(Volt(2.048)).unwrap();
+        releaser.join().unwrap();
+        spi.release();
+        let (reading, ready) = read(ready, Channel::Ch3);
+        assert_eq!(reading.raw, 400);
+        assert_volts(reading.value, 400.0 * 2.048 / 1024.0);
+        assert_eq!(ready.inner.stale_replies, 0);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn is_ready_ignores_a_queued_late_reply() {
+        let (spi, ready) = gated();
+        let ready = time_out_probe(&spi, ready, 1);
+
+        // the late reply arrives while the next probe is held in its own transfer
+        spi.release();
+        let mut probing = ready.probe_channel(Channel::Ch1).unwrap();
+        loop {
+            match probing.poll() {
+                Ok(ProbingOp::Idle(next)) if matches!(next.state, ProbingState::WaitingReply) => {
+                    probing = next;
+                    break;
+                },
+                Ok(ProbingOp::Idle(next)) =>
+                    probing = next,
+                _ =>
+                    panic!("probe finished while its transfer is held"),
+            }
+        }
+        spi.wait_entered(2);
+        assert!(!probing.is_ready());
+        assert!(!probing.is_ready());
+
+        spi.release();
+        while !probing.is_ready() {
+            thread::sleep(Duration::from_millis(1));
         }
+        let ready = match probing.poll() {
+            Ok(ProbingOp::Done { channel: Channel::Ch1, reading, ready, .. }) => {
+                assert_eq!(reading.raw, 200);
+                ready
+            },
+            _ =>
+                panic!("poll after is_ready did not hand out the reply"),
+        };
+        assert_eq!(ready.inner.stale_replies, 0);
+        assert!(ready.inner.details.peeked.borrow().is_empty());
+
+        // nothing is left over to be mistaken for the reply to the next probe
+        spi.release();
+        let (reading, ready) = read(ready, Channel::Ch2);
+        assert_eq!(reading.raw, 300);
+
+        Session::from(ready).shutdown();
+    }
+
+    #[test]
+    fn mock_counts_follow_updates() {
+        let params = Params::new(Vdd::Positive3v3, Vref::Other { voltage: Volt(2.048), });
+        let mock = MockSpi::new([0; 8]);
+        let ready = Session::open_with_backend(&params, Box::new(mock.clone())).unwrap();
+
+        mock.set(Channel::Ch4, 256);
+        let (reading, ready) = read(ready, Channel::Ch4);
+        assert_eq!(reading.raw, 256);
+        assert_volts(reading.value, 0.512);
+
+        Session::from(ready).shutdown();
     }
 }
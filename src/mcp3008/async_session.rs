@@ -0,0 +1,151 @@
+use tokio::{
+    sync::{
+        mpsc,
+        oneshot,
+    },
+    task,
+};
+
+use rpi_lfa::Volt;
+
+use super::{
+    open_backend,
+    run_self_test,
+    sample_channel,
+    Chip,
+    Error,
+    Config,
+    Params,
+    Channel,
+    SpiTransfer,
+    SelfTestFailure,
+    DifferentialPair,
+};
+
+// the whole typestate collapses into `&self` methods here: awaiting the reply does the waiting,
+// so concurrent probes simply queue up on the request channel
+pub struct AsyncSession {
+    request_tx: mpsc::Sender<Request>,
+    chip: Chip,
+    worker: task::JoinHandle<()>,
+}
+
+impl AsyncSession {
+    // resolves once the spi peripheral is opened, so initialization errors surface right here
+    pub async fn new(params: &Params) -> Result<AsyncSession, Error> {
+        AsyncSession::spawn(params, None).await
+    }
+
+    pub async fn with_backend(params: &Params, backend: Box<dyn SpiTransfer + Send>) -> Result<AsyncSession, Error> {
+        AsyncSession::spawn(params, Some(backend)).await
+    }
+
+    // `thread_name` and `stack_size` don't apply: the worker runs on tokio's blocking pool
+    async fn spawn(params: &Params, backend: Option<Box<dyn SpiTransfer + Send>>) -> Result<AsyncSession, Error> {
+        let config = Config::new(params)?;
+        let chip = config.chip;
+        let (request_tx, request_rx) = mpsc::channel(1);
+        let (init_tx, init_rx) = oneshot::channel();
+        let worker = task::spawn_blocking(move || spi_worker(request_rx, init_tx, backend, config));
+        match init_rx.await {
+            Ok(Ok(())) =>
+                Ok(AsyncSession { request_tx, chip, worker, }),
+            Ok(Err(error)) =>
+                Err(error),
+            Err(oneshot::error::RecvError { .. }) =>
+                Err(Error::SpiThreadLost),
+        }
+    }
+
+    pub async fn probe_channel(&self, channel: Channel) -> Result<Volt, Error> {
+        self.chip.ensure_supported(channel)?;
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(Request::ProbeChannel { channel, reply_tx, }, reply_rx).await?
+    }
+
+    pub async fn probe_differential(&self, pair: DifferentialPair) -> Result<Volt, Error> {
+        self.chip.ensure_supported(pair.positive())?;
+        self.chip.ensure_supported(pair.negative())?;
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(Request::ProbeDifferential { pair, reply_tx, }, reply_rx).await?
+    }
+
+    // the latest failed `Params::self_test` since the previous call, earlier ones are overwritten
+    pub async fn take_self_test_failure(&self) -> Result<Option<SelfTestFailure>, Error> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(Request::TakeSelfTestFailure { reply_tx, }, reply_rx).await
+    }
+
+    // closes the request channel and waits for the worker to release the peripheral
+    pub async fn shutdown(self) -> Result<(), Error> {
+        let AsyncSession { request_tx, worker, .. } = self;
+        drop(request_tx);
+        worker.await
+            .map_err(|task::JoinError { .. }| Error::SpiThreadLost)
+    }
+
+    async fn request<T>(&self, request: Request, reply_rx: oneshot::Receiver<T>) -> Result<T, Error> {
+        self.request_tx.send(request).await
+            .map_err(|mpsc::error::SendError(..)| Error::SpiThreadLost)?;
+        reply_rx.await
+            .map_err(|oneshot::error::RecvError { .. }| Error::SpiThreadLost)
+    }
+}
+
+// every request carries its own reply slot, so a reading can never be handed to the wrong caller
+enum Request {
+    ProbeChannel { channel: Channel, reply_tx: oneshot::Sender<Result<Volt, Error>>, },
+    ProbeDifferential { pair: DifferentialPair, reply_tx: oneshot::Sender<Result<Volt, Error>>, },
+    TakeSelfTestFailure { reply_tx: oneshot::Sender<Option<SelfTestFailure>>, },
+}
+
+// unlike the threaded `Session` a failed transfer is only reported to the caller which asked for it,
+// the worker keeps serving subsequent requests
+fn spi_worker(
+    mut request_rx: mpsc::Receiver<Request>,
+    init_tx: oneshot::Sender<Result<(), Error>>,
+    backend: Option<Box<dyn SpiTransfer + Send>>,
+    mut config: Config,
+)
+{
+    let spi = match backend {
+        Some(backend) =>
+            backend,
+        None =>
+            match open_backend(&config) {
+                Ok(spi) =>
+                    spi,
+                Err(error) => {
+                    init_tx.send(Err(error)).ok();
+                    return;
+                },
+            },
+    };
+    let spi = &*spi;
+    let mut buffer: [u8; 3] = [0, 0, 0];
+
+    #[cfg(feature = "log")]
+    log::debug!("spi initialized on {:?}/{:?} at {} Hz", config.bus, config.slave_select, config.hz);
+
+    if init_tx.send(Ok(())).is_err() {
+        return;
+    }
+
+    while let Some(request) = request_rx.blocking_recv() {
+        match request {
+            Request::ProbeChannel { channel, reply_tx, } => {
+                let result = run_self_test(spi, &mut buffer, &mut config)
+                    .and_then(|()| sample_channel(spi, &mut buffer, channel, true, &config));
+                reply_tx.send(result.map(|reading| reading.value)).ok();
+            },
+            Request::ProbeDifferential { pair, reply_tx, } => {
+                let result = run_self_test(spi, &mut buffer, &mut config)
+                    .and_then(|()| sample_channel(spi, &mut buffer, pair.positive(), false, &config));
+                reply_tx.send(result.map(|reading| reading.value)).ok();
+            },
+            Request::TakeSelfTestFailure { reply_tx, } => {
+                reply_tx.send(config.self_test_failure.take()).ok();
+            },
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use embedded_hal::adc;
+
+use super::{
+    Ready,
+    Error,
+    Channel,
+};
+
+pub struct Mcp3008;
+
+// `adc::Channel` identifies pins at type level, so each channel gets its own marker type
+// carrying the runtime `Channel` as its id
+pub struct Ch0;
+pub struct Ch1;
+pub struct Ch2;
+pub struct Ch3;
+pub struct Ch4;
+pub struct Ch5;
+pub struct Ch6;
+pub struct Ch7;
+
+macro_rules! impl_channel {
+    ($pin:ident) => {
+        impl adc::Channel<Mcp3008> for $pin {
+            type ID = Channel;
+
+            fn channel() -> Channel {
+                Channel::$pin
+            }
+        }
+    };
+}
+
+impl_channel!(Ch0);
+impl_channel!(Ch1);
+impl_channel!(Ch2);
+impl_channel!(Ch3);
+impl_channel!(Ch4);
+impl_channel!(Ch5);
+impl_channel!(Ch6);
+impl_channel!(Ch7);
+
+pub struct OneShotAdc {
+    ready: Ready,
+}
+
+impl OneShotAdc {
+    pub fn new(ready: Ready) -> OneShotAdc {
+        OneShotAdc { ready, }
+    }
+
+    pub fn into_ready(self) -> Ready {
+        self.ready
+    }
+}
+
+impl From<Ready> for OneShotAdc {
+    fn from(ready: Ready) -> OneShotAdc {
+        OneShotAdc::new(ready)
+    }
+}
+
+impl<P> adc::OneShot<Mcp3008, u16, P> for OneShotAdc where P: adc::Channel<Mcp3008, ID = Channel> {
+    type Error = Error;
+
+    // reads are performed through the blocking path, so this never returns `WouldBlock`
+    fn read(&mut self, _pin: &mut P) -> nb::Result<u16, Error> {
+        let reading = self.ready.read_blocking_reading(P::channel())
+            .map_err(nb::Error::Other)?;
+        Ok(reading.raw)
+    }
+}
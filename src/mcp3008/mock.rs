@@ -0,0 +1,107 @@
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    time::Instant,
+};
+
+use rpi_lfa::Volt;
+
+use super::{
+    Channel,
+    Waveform,
+    FrameLayout,
+    SpiTransfer,
+};
+
+// answers every conversion with a canned count for the requested channel; clones share
+// the counts, so a test can keep one to change readings while the session owns the other
+#[derive(Clone, Default)]
+pub struct MockSpi {
+    counts: Arc<Mutex<[u16; 8]>>,
+}
+
+impl MockSpi {
+    // indexed by channel number, counts are truncated to the resolution of the framing being requested
+    pub fn new(counts: [u16; 8]) -> MockSpi {
+        MockSpi { counts: Arc::new(Mutex::new(counts)), }
+    }
+
+    pub fn set(&self, channel: Channel, count: u16) {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts[u8::from(channel) as usize] = count;
+    }
+}
+
+impl SpiTransfer for MockSpi {
+    fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), rppal::spi::Error> {
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        respond(read_buffer, write_buffer, |index| counts[index]);
+        Ok(())
+    }
+}
+
+// what `Backend::Simulated` runs on: every channel follows its waveform from the moment of creation,
+// converted against `v_ref` the way the real chip would, with commands and results framed by `layout`
+pub struct SimulatedSpi {
+    waveforms: [Waveform; 8],
+    v_ref: Volt,
+    layout: FrameLayout,
+    started: Instant,
+}
+
+impl SimulatedSpi {
+    // indexed by channel number
+    pub fn new(waveforms: [Waveform; 8], v_ref: Volt, layout: FrameLayout) -> SimulatedSpi {
+        SimulatedSpi { waveforms, v_ref, layout, started: Instant::now(), }
+    }
+}
+
+impl SpiTransfer for SimulatedSpi {
+    fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), rppal::spi::Error> {
+        let elapsed = self.started.elapsed();
+        let mut command = [0; 3];
+        command[.. write_buffer.len()].copy_from_slice(write_buffer);
+        let word = FrameLayout::word(&command);
+        // parts with fewer channels have other command bits right above the index
+        let index_bits = word & !FrameLayout::word(&self.layout.command) & !(1 << self.layout.single_ended_bit);
+        let index = (index_bits >> self.layout.channel_shift) as usize & 0b111;
+        let mut voltage = self.waveforms[index].voltage_at(elapsed).0;
+        if word & 1 << self.layout.single_ended_bit == 0 {
+            // pseudo-differential: the channel is IN+ and the other one of its pair IN-
+            voltage -= self.waveforms[index ^ 1].voltage_at(elapsed).0;
+        }
+        let full_scale = (1u32 << self.layout.result_width()) as f64;
+        let count = (voltage / self.v_ref.0 * full_scale).clamp(0.0, full_scale - 1.0) as u32;
+        let response = ((count & self.layout.result_mask as u32) << self.layout.result_shift).to_be_bytes();
+        read_buffer.copy_from_slice(&response[1 .. 1 + read_buffer.len()]);
+        Ok(())
+    }
+}
+
+// decodes the channel from the command in any of the standard `Chip` framings and encodes `count(index)`
+// into the response, truncated to the resolution of that framing
+fn respond<F>(read_buffer: &mut [u8], write_buffer: &[u8], count: F) where F: Fn(usize) -> u16 {
+    if write_buffer.len() == 2 {
+        // MCP3002 framing: ODD/SIGN right after SGL/DIFF selects the channel, the result ends the first byte
+        let count = count(((write_buffer[0] >> 4) & 0b00000001) as usize) & 0b0000001111111111;
+        read_buffer[0] = (count >> 8) as u8;
+        read_buffer[1] = count as u8;
+        return;
+    }
+    if write_buffer[0] & 0b00000100 == 0 {
+        // 10-bit framing: D2..D0 follow the SGL/DIFF bit in the second command byte
+        let count = count(((write_buffer[1] >> 4) & 0b00000111) as usize) & 0b0000001111111111;
+        read_buffer[1] = (count >> 8) as u8;
+        read_buffer[2] = count as u8;
+    } else {
+        // 12-bit framing: D2 ends the first command byte, D1 and D0 start the second one
+        let index = (write_buffer[0] & 0b00000001) << 2 | write_buffer[1] >> 6;
+        let count = count(index as usize) & 0b0000111111111111;
+        read_buffer[1] = (count >> 8) as u8;
+        read_buffer[2] = count as u8;
+    }
+    read_buffer[0] = 0;
+}
@@ -0,0 +1,78 @@
+use serde::{
+    Serialize,
+    Serializer,
+    Deserialize,
+    Deserializer,
+};
+
+use super::{
+    Bus,
+    Vdd,
+    Mode,
+    Volt,
+    Vref,
+    Params,
+    SlaveSelect,
+};
+
+// fields missing from a serialized `Params` take the values `Params::builder` starts from,
+// so configs written before a field existed keep loading
+pub fn params() -> Params {
+    Params::new(Vdd::Positive3v3, Vref::EqualToVdd)
+}
+
+// rppal and rpi_lfa types have no serde support, so they are mirrored here for `#[serde(with)]`
+
+pub mod volt {
+    use super::*;
+
+    pub fn serialize<S>(volt: &Volt, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        volt.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Volt, D::Error> where D: Deserializer<'de> {
+        f64::deserialize(deserializer).map(Volt)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Bus")]
+pub enum BusDef {
+    Spi0,
+    Spi1,
+    Spi2,
+    Spi3,
+    Spi4,
+    Spi5,
+    Spi6,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SlaveSelect")]
+pub enum SlaveSelectDef {
+    Ss0,
+    Ss1,
+    Ss2,
+    Ss3,
+    Ss4,
+    Ss5,
+    Ss6,
+    Ss7,
+    Ss8,
+    Ss9,
+    Ss10,
+    Ss11,
+    Ss12,
+    Ss13,
+    Ss14,
+    Ss15,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Mode")]
+pub enum ModeDef {
+    Mode0,
+    Mode1,
+    Mode2,
+    Mode3,
+}